@@ -1,703 +1,7017 @@
-use clap::Parser;
+// explicit `return` statements are the preferred style in this codebase
+#![allow(clippy::needless_return)]
+
+use clap::{CommandFactory, Parser, Subcommand, ValueEnum};
 use either::{Either, Left, Right};
+use encoding_rs::Encoding;
+use ignore::WalkBuilder;
+use indicatif::{MultiProgress, ProgressBar, ProgressStyle};
 use lazy_static::lazy_static;
-use mustache;
+use log::{debug, error, info, trace, warn};
 use mustache::MapBuilder;
+use notify::{RecursiveMode, Watcher};
+use rayon::prelude::*;
 use regex::Regex;
 use semver::{Version, VersionReq};
 use serde::{Deserialize, Deserializer, Serialize};
+use similar::TextDiff;
+use std::cell::RefCell;
 use std::cmp::Ordering;
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap, VecDeque};
 use std::fs;
 use std::fs::File;
 use std::io;
-use std::io::{BufRead, Write};
+use std::io::{IsTerminal, Read, Write};
 use std::num::ParseFloatError;
 use std::path::{Path, PathBuf};
 use std::process::ExitCode;
-use validator::{Validate, ValidationError};
-use walkdir::WalkDir;
+use std::sync::atomic::{self, AtomicUsize};
+use std::sync::{mpsc, Mutex};
+use std::time::{Duration, SystemTime};
+use unicode_normalization::UnicodeNormalization;
+use validator::{Validate, ValidationError, ValidationErrors, ValidationErrorsKind};
 use wax::{Glob, Pattern};
 
 /// Extracts doc strings into markdown files
 ///
 /// Walks through all files in `<SOURCE>` and searches for comments. With comments, looks
 /// for `@file [file]` on its own line and if present the contents of the comment are
-/// appended to the specified file path. The file and its directories are created at the
+/// appended to the specified file path (`@file` can be repeated, or given a list such as
+/// `@file a.md, b.md`, to append them to several files; only commas followed by a space
+/// separate the files, so that `@file a,b.md` is one file). The file and its directories are created at the
 /// given `<DEST>`. Optionally, you can provide `@order [num]` on its own line to influence
 /// the ordering of the comment content. Content is sorted from the lowest to the highest
-/// `order`, breaking ties by pre-sorted ordering. Additional `@` prefixed tags will be
-/// excluded from the output. All text on preceding lines is included as part of the tag
-/// until an empty line or a new tag is encountered. The additional tags don't do anything
-/// unless you define an appropriate configuration template (See README.md for details). You
-/// can configure what is considered a comment for a given file extension in your config
-/// file.
+/// `order`, breaking ties by pre-sorted ordering. With `tags.order = "dotted"` in the
+/// config file, an `order` can instead be a dotted list of whole numbers such as `2.1.3`,
+/// compared one number at a time, so that `2` comes before `2.1` and `2.9` before `2.10`.
+/// Additional `@` prefixed tags will be excluded from the output. All text on preceding
+/// lines is included as part of the tag until an empty line or a new tag is encountered.
+/// The additional tags don't do anything unless you define an appropriate configuration
+/// template (See README.md for details). You can configure what is considered a comment for
+/// a given file extension in your config file. A line holding only `@end` (or, within an `@if`
+/// section, `---`) splits a comment into separate doc blocks, each with its own tags, and `@include [file]`
+/// splices the content of a file (relative to the source file, and able to `@include`
+/// others in turn) into the comment. Similarly, `@snippet [file] [region]` inserts the
+/// lines of a file between `BEGIN [region]` and `END [region]` markers (e.g.
+/// `// BEGIN example`) as a fenced code block.
+///
+/// Most options can also be set through an environment variable (shown in `--help`), which
+/// is used when the option isn't passed on the command line. Flags are enabled by setting
+/// their variable to `true`.
 #[derive(Parser, Debug)]
-#[command(version, about, long_about)]
+#[command(version, about, long_about, subcommand_precedence_over_arg = true)]
 struct Args {
+    #[command(subcommand)]
+    command: Option<Command>,
+
     /// root directory where markdown files are generated
-    #[arg(short, long, default_value = ".")]
+    #[arg(
+        short,
+        long,
+        default_value = ".",
+        global = true,
+        env = "SIMPLE_SRC_DOCS_DEST"
+    )]
     dest: PathBuf,
 
-    /// if set, show detailed messages about document processing
-    #[arg(short, long)]
-    verbose: bool,
+    /// show detailed messages about document processing: `-v` reports each file read and
+    /// written, `-vv` also reports each doc block and template. Messages can be filtered
+    /// further with `SIMPLE_SRC_DOCS_LOG` (e.g. `SIMPLE_SRC_DOCS_LOG=templates=trace`)
+    #[arg(short, long, action = clap::ArgAction::Count, global = true)]
+    verbose: u8,
+
+    /// only show errors
+    #[arg(
+        short,
+        long,
+        global = true,
+        conflicts_with = "verbose",
+        env = "SIMPLE_SRC_DOCS_QUIET"
+    )]
+    quiet: bool,
+
+    /// how warnings, errors and other messages are printed; with `json`, each message is
+    /// written to standard error as a JSON object with the fields `severity`, `message`,
+    /// `file` and `line`
+    #[arg(
+        long,
+        value_enum,
+        default_value_t = MessageFormat::Human,
+        global = true,
+        env = "SIMPLE_SRC_DOCS_MESSAGE_FORMAT"
+    )]
+    message_format: MessageFormat,
 
     /// location of file used to further configure simple-src-docs
-    /// (see README.md), defaults to `<DEST>/.simple-src-docs.config.toml
-    #[arg(long)]
+    /// (see README.md), defaults to `<DEST>/.simple-src-docs.config.toml`, or else the
+    /// nearest `.simple-src-docs.config.toml` in a `<SOURCE>` directory or its parents
+    #[arg(long, global = true, env = "SIMPLE_SRC_DOCS_CONFIG")]
     config: Option<PathBuf>,
 
-    /// the source directories or files where comments will be extracted from
+    /// print the configuration in effect (the config file, with any `--set` overrides and
+    /// the built-in comment syntaxes appended as TOML comments) as TOML, then exit
+    #[arg(long, global = true)]
+    print_config: bool,
+
+    /// override a setting of the config file, given as a dotted path to the setting and a
+    /// TOML value (strings need no quotes), e.g. `--set template.foreach.0.file=api.md`
+    /// (can be repeated)
+    #[arg(long, value_name = "KEY=VALUE", global = true)]
+    set: Vec<String>,
+
+    /// if set, keep extracted docs in `<DEST>/.simple-src-docs.cache` so that only source
+    /// files that changed since the last run are parsed again
+    #[arg(long, global = true, env = "SIMPLE_SRC_DOCS_CACHE")]
+    cache: bool,
+
+    /// a glob of files or directories to skip when walking `<SOURCE>`, matched against
+    /// their path relative to the source directory or their name (can be repeated)
+    #[arg(long, value_name = "GLOB", global = true)]
+    exclude: Vec<String>,
+
+    /// if given, only read files in `<SOURCE>` matching this glob, compared against their
+    /// path relative to the source directory or their name (can be repeated)
+    #[arg(long, value_name = "GLOB", global = true)]
+    include: Vec<String>,
+
+    /// include the sections of comments between `@if NAME` and `@endif` for this name, in
+    /// addition to the names given by `define` in the config file (can be repeated)
+    #[arg(long, value_name = "NAME", global = true)]
+    define: Vec<String>,
+
+    /// if set, skip files and directories ignored by git (through `.gitignore` files, the
+    /// repository's `info/exclude` or the global git excludes file)
+    #[arg(long, global = true, env = "SIMPLE_SRC_DOCS_RESPECT_GITIGNORE")]
+    respect_gitignore: bool,
+
+    /// if set, follow symbolic links to directories when walking `<SOURCE>`; links that
+    /// lead back to one of their own parent directories are skipped
+    #[arg(long, global = true, env = "SIMPLE_SRC_DOCS_FOLLOW_SYMLINKS")]
+    follow_symlinks: bool,
+
+    /// read the source files to extract comments from (one path per line) from this file,
+    /// or from standard input with `-`; these are read in addition to any `<SOURCE>` and
+    /// are not filtered by `--include` or `--exclude`
+    #[arg(
+        long,
+        value_name = "PATH",
+        global = true,
+        env = "SIMPLE_SRC_DOCS_FILES_FROM"
+    )]
+    files_from: Option<PathBuf>,
+
+    /// the maximum depth of directories below each `<SOURCE>` to walk into
+    #[arg(
+        long,
+        value_name = "N",
+        global = true,
+        env = "SIMPLE_SRC_DOCS_MAX_DEPTH"
+    )]
+    max_depth: Option<usize>,
+
+    /// skip source files larger than this many bytes (such as generated or vendored code),
+    /// with a warning; overrides `max_file_size` in the config file
+    #[arg(
+        long,
+        value_name = "BYTES",
+        global = true,
+        env = "SIMPLE_SRC_DOCS_MAX_FILE_SIZE"
+    )]
+    max_file_size: Option<u64>,
+
+    /// if set, fail when any warnings are reported (such as an invalid `@order` or a tagged
+    /// doc block that no template uses) before writing anything
+    #[arg(long, global = true, env = "SIMPLE_SRC_DOCS_STRICT")]
+    strict: bool,
+
+    /// the maximum number of source files read in parallel, defaults to the number of
+    /// available CPUs
+    #[arg(short, long, global = true, env = "SIMPLE_SRC_DOCS_JOBS")]
+    jobs: Option<usize>,
+
+    /// print a man page (in roff format) for simple-src-docs to standard output
+    #[arg(long, hide = true)]
+    generate_man: bool,
+
+    /// write a summary of the run (files scanned, docs extracted, outputs written and
+    /// warnings reported) to this file once done; it is written as markdown if the file
+    /// name ends in `.md` and as JSON otherwise
+    #[arg(
+        long,
+        value_name = "PATH",
+        global = true,
+        env = "SIMPLE_SRC_DOCS_REPORT"
+    )]
+    report: Option<PathBuf>,
+
+    /// the file extension (e.g. `rs`) used to select the comment syntax when reading
+    /// source content from standard input
+    #[arg(long, global = true, env = "SIMPLE_SRC_DOCS_STDIN_EXT")]
+    stdin_ext: Option<String>,
+
+    /// options for running without a subcommand, which is the same as `generate`
+    #[command(flatten)]
+    generate: GenerateArgs,
+}
+
+#[derive(clap::Args, Debug)]
+struct GenerateArgs {
+    /// if set, report the files that would be written (with their size) without writing
+    /// anything to `<DEST>`
+    #[arg(long)]
+    dry_run: bool,
+
+    /// same as the `check` subcommand, kept for existing scripts
+    #[arg(long, hide = true, conflicts_with = "dry_run")]
+    check: bool,
+
+    /// if set, print a unified diff between the files in `<DEST>` and the documentation
+    /// that would be generated, without writing anything
+    #[arg(long, conflicts_with_all = ["dry_run", "check"])]
+    diff: bool,
+
+    /// if set, print the generated content of the given output file (relative to `<DEST>`)
+    /// to standard output instead of writing any files
+    #[arg(long, value_name = "FILE", conflicts_with_all = ["dry_run", "check", "diff"])]
+    stdout: Option<PathBuf>,
+
+    /// if set, delete files generated by a previous run (as recorded in
+    /// `<DEST>/.simple-src-docs.manifest`) that are no longer generated
+    #[arg(long)]
+    clean: bool,
+
+    /// the source directories or files where comments will be extracted from; use `-` to
+    /// read source content from standard input (see `--stdin-ext`)
     source: Vec<PathBuf>,
 }
 
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq)]
+enum MessageFormat {
+    Human,
+    Json,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Generate the documentation (the default when no subcommand is given)
+    ///
+    /// Extracts the doc blocks of `<SOURCE>` and writes the resulting markdown files to
+    /// `<DEST>`, or reports what would change with `--dry-run`, `--diff` or `--stdout`.
+    Generate(GenerateArgs),
+    /// Check that the generated documentation is up to date
+    ///
+    /// Compares the documentation that would be generated from `<SOURCE>` against the files
+    /// in `<DEST>` without writing anything, and fails if any of them are missing or out of
+    /// date.
+    Check {
+        /// the source directories or files where comments will be extracted from; use `-`
+        /// to read source content from standard input (see `--stdin-ext`)
+        source: Vec<PathBuf>,
+    },
+    /// Write a commented configuration file to get started
+    ///
+    /// The file is written to the path given by `--config`, or to
+    /// `<DEST>/.simple-src-docs.config.toml`. It lists the built-in comment syntaxes and
+    /// example templates, all commented out.
+    Init {
+        /// overwrite the configuration file if it already exists
+        #[arg(long)]
+        force: bool,
+    },
+    /// Check the configuration file for errors
+    ///
+    /// Parses the file given by `--config` (or `<DEST>/.simple-src-docs.config.toml`),
+    /// checks its header and comment settings and compiles every template, reporting all
+    /// problems found.
+    Validate,
+    /// Upgrade the configuration file to the current version
+    ///
+    /// Rewrites the file given by `--config` (or `<DEST>/.simple-src-docs.config.toml`) when
+    /// its `header.version` is older than the current one: deprecated keys are replaced by
+    /// their current names and the version is updated. TOML files keep their comments and
+    /// layout.
+    MigrateConfig {
+        /// print the migrated configuration instead of writing it back to the file
+        #[arg(long)]
+        print: bool,
+    },
+    /// List the tags found in the source files
+    ///
+    /// Prints every `@` prefixed tag found in the doc blocks of `<SOURCE>`, along with the
+    /// number of blocks it appears in and the files those blocks come from.
+    Tags {
+        /// the source directories or files to search for tags; use `-` to read source
+        /// content from standard input (see `--stdin-ext`)
+        source: Vec<PathBuf>,
+    },
+    /// Summarize how much documentation is extracted from the source files
+    ///
+    /// Reports the number of files scanned and skipped, the doc blocks extracted from them
+    /// (and how many lack an `@file` tag) and the number of output files produced, without
+    /// writing anything.
+    Stats {
+        /// the source directories or files to summarize; use `-` to read source content
+        /// from standard input (see `--stdin-ext`)
+        source: Vec<PathBuf>,
+    },
+    /// Delete generated files that are no longer produced from the source files
+    ///
+    /// Files written by previous runs are recorded in `<DEST>/.simple-src-docs.manifest`;
+    /// any of them that would not be generated from `<SOURCE>` are deleted, along with any
+    /// directories left empty. No other files are written.
+    Clean {
+        /// the source directories or files where comments will be extracted from; use `-`
+        /// to read source content from standard input (see `--stdin-ext`)
+        source: Vec<PathBuf>,
+    },
+    /// Regenerate the documentation whenever the source files change
+    ///
+    /// Generates the documentation once, then watches `<SOURCE>` and the configuration file
+    /// (along with the files it includes) and generates it again after each change, until
+    /// interrupted. The configuration is reloaded and validated again on each change. Errors
+    /// are reported without stopping.
+    Watch {
+        /// if set, delete files generated by a previous run that are no longer generated
+        #[arg(long)]
+        clean: bool,
+
+        /// the source directories or files where comments will be extracted from
+        source: Vec<PathBuf>,
+    },
+    /// Print a shell completion script
+    ///
+    /// Writes a script to standard output that completes the options and subcommands of
+    /// simple-src-docs for the given shell, e.g. `simple-src-docs completions bash >
+    /// ~/.local/share/bash-completion/completions/simple-src-docs`.
+    Completions {
+        /// the shell to generate completions for
+        shell: clap_complete::Shell,
+    },
+}
+
+impl Args {
+    /// The `<SOURCE>`s given to the subcommand, or else to the top-level command
+    fn sources(&self) -> &[PathBuf] {
+        return match &self.command {
+            Some(Command::Generate(generate)) => &generate.source,
+            Some(
+                Command::Check { source }
+                | Command::Tags { source }
+                | Command::Stats { source }
+                | Command::Clean { source }
+                | Command::Watch { source, .. },
+            ) => source,
+            _ => &self.generate.source,
+        };
+    }
+}
+
+#[derive(Debug)]
 struct SrcDocError {
     msg: String,
-    code: std::process::ExitCode,
+    /// the file (and line in that file) the error relates to, if any
+    file: Option<PathBuf>,
+    line: Option<usize>,
+    kind: ErrorKind,
+}
+
+/// The category of an error, which determines the exit code (see README.md)
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum ErrorKind {
+    /// failures that aren't errors in themselves, such as out of date documentation found by
+    /// `check` or warnings reported in strict mode
+    Failure = 1,
+    /// invalid command line arguments (the same code clap uses)
+    Usage = 2,
+    /// problems reading or writing files
+    Io = 3,
+    /// a config file that can't be parsed
+    Config = 4,
+    /// a config file that parses but contains invalid settings
+    Validation = 5,
+    /// templates that can't be compiled or rendered
+    Template = 6,
+    /// invalid doc blocks in the source files
+    Source = 7,
 }
 
 impl SrcDocError {
     fn new(msg: String) -> SrcDocError {
         return SrcDocError {
             msg,
-            code: ExitCode::FAILURE,
+            file: None,
+            line: None,
+            kind: ErrorKind::Failure,
         };
     }
+
+    fn with_file(mut self, file: &Path) -> SrcDocError {
+        self.file = Some(file.to_path_buf());
+        return self;
+    }
+
+    fn with_line(mut self, line: usize) -> SrcDocError {
+        self.line = Some(line);
+        return self;
+    }
+
+    fn with_kind(mut self, kind: ErrorKind) -> SrcDocError {
+        self.kind = kind;
+        return self;
+    }
 }
 
 fn exit_code(x: Result<(), SrcDocError>) -> ExitCode {
     match x {
-        Ok(_) => {
-            println!("Successfully generated documentation.");
-            return ExitCode::SUCCESS;
-        }
+        Ok(_) => return ExitCode::SUCCESS,
         Err(e) => {
-            eprintln!("{}", e.msg);
-            return e.code;
+            report_error(&e);
+            return ExitCode::from(e.kind as u8);
         }
     };
 }
 
+fn report_error(e: &SrcDocError) {
+    match (&e.file, e.line) {
+        (Some(file), Some(line)) => error!(file:% = file.display(), line; "{}", e.msg),
+        (Some(file), None) => error!(file:% = file.display(); "{}", e.msg),
+        _ => error!("{}", e.msg),
+    }
+}
+
 fn main() -> ExitCode {
-    return exit_code(run());
+    let args = Args::parse();
+    init_logger(&args);
+    let result = run(&args);
+    if let Some(path) = &args.report {
+        let error = result.as_ref().err().map(|e| e.msg.as_str());
+        if let Err(e) = write_report(path, error) {
+            return exit_code(Err(e));
+        }
+    }
+    return exit_code(result);
 }
 
 impl From<io::Error> for SrcDocError {
     fn from(e: io::Error) -> SrcDocError {
-        return SrcDocError::new(format!("IO Error: {}", e));
+        return SrcDocError::new(format!("IO Error: {}", e)).with_kind(ErrorKind::Io);
     }
 }
 
 impl From<toml::de::Error> for SrcDocError {
     fn from(e: toml::de::Error) -> SrcDocError {
-        return SrcDocError::new(format!("Config Error: {}", e));
+        let msg = format!("Config Error: {}{}", e, suggest_field(e.message()));
+        return SrcDocError::new(msg).with_kind(ErrorKind::Config);
+    }
+}
+
+/// For an error about an unknown field, suggests the closest of the expected fields, e.g.
+/// `output` for `ouput`
+fn suggest_field(msg: &str) -> String {
+    let field_r = Regex::new(r"unknown field `(?<field>[^`]*)`, expected (?<expected>.*)").unwrap();
+    let Some(m) = field_r.captures(msg) else {
+        return String::new();
+    };
+    let field = &m["field"];
+    let closest = Regex::new(r"`([^`]*)`")
+        .unwrap()
+        .captures_iter(&m["expected"])
+        .map(|c| c.get(1).unwrap().as_str())
+        .min_by_key(|name| edit_distance(field, name));
+    return match closest {
+        Some(name) if edit_distance(field, name) <= (field.len() / 3).max(1) => {
+            format!("did you mean `{}`?\n", name)
+        }
+        _ => String::new(),
+    };
+}
+
+/// The number of single character insertions, deletions or substitutions that turn `a`
+/// into `b`
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut row: Vec<usize> = (0..=b.len()).collect();
+    for (i, ca) in a.chars().enumerate() {
+        let mut previous = row[0];
+        row[0] = i + 1;
+        for (j, cb) in b.iter().enumerate() {
+            let substitution = previous + usize::from(ca != *cb);
+            previous = row[j + 1];
+            row[j + 1] = substitution.min(row[j] + 1).min(row[j + 1] + 1);
+        }
+    }
+    return row[b.len()];
+}
+
+impl From<ValidationErrors> for SrcDocError {
+    fn from(e: ValidationErrors) -> SrcDocError {
+        let mut problems = Vec::new();
+        validation_problems("", &e, &mut problems);
+        return SrcDocError::new(format!("Config Error: {}", problems.join("; ")))
+            .with_kind(ErrorKind::Validation);
     }
 }
 
-impl From<walkdir::Error> for SrcDocError {
-    fn from(e: walkdir::Error) -> SrcDocError {
-        return SrcDocError::new(format!("Error traversing directories: {}", e));
+impl From<ignore::Error> for SrcDocError {
+    fn from(e: ignore::Error) -> SrcDocError {
+        return SrcDocError::new(format!("Error traversing directories: {}", e))
+            .with_kind(ErrorKind::Io);
     }
 }
 
+/// The docs extracted from a single source file
+struct SourceFile {
+    path: PathBuf,
+    docs: Vec<DocData>,
+    /// true if no comment syntax is configured for this file
+    skipped: bool,
+}
+
+/// Reads the docs of `file`, or takes them from the `cache` if it is unchanged; files larger
+/// than `max_size` are skipped, and left out of the cache
 fn read_comments(
-    args: &Args,
     config: &SrcDocConfig,
     file: &Path,
-    docs: &mut Vec<DocData>,
-) -> Result<(), SrcDocError> {
-    let io = File::open(file)?;
-    let reader = io::BufReader::new(io);
-    let str_lines = reader.lines().map_while(Result::ok);
-    if args.verbose {
-        println!("Reading file {}", file.to_str().unwrap());
-    }
-    let comment_config = config.find_comment_config(file);
-    if let Some(c) = comment_config {
-        let comments = Comments::new(str_lines, c);
-        for d in DocIterator::new(comments) {
-            if args.verbose {
-                println!("Found comment with tags:");
-                for (k, v) in &d.tags {
-                    println!("@{}: {}", k, v);
-                }
-            }
-            docs.push(d);
-        }
-        return Ok(());
-    } else {
-        if args.verbose {
-            println!("Skipping file without a matching extension");
-        }
-        return Ok(());
+    cache: &DocCache,
+    max_size: Option<u64>,
+) -> Result<(SourceFile, Option<CachedFile>), SrcDocError> {
+    let metadata = fs::metadata(file).map_err(|e| SrcDocError::from(e).with_file(file))?;
+    let modified = metadata.modified().ok();
+    let size = metadata.len();
+    if let Some(max) = max_size.filter(|max| size > *max) {
+        warn!(
+            file:% = file.display();
+            "Skipping a file of {} bytes, larger than the maximum file size ({} bytes)",
+            size, max
+        );
+        let source = SourceFile {
+            path: file.to_path_buf(),
+            docs: Vec::new(),
+            skipped: true,
+        };
+        return Ok((source, None));
+    }
+    let cached = cache.files.get(file).filter(|c| c.includes_unchanged());
+    if let Some(c) =
+        cached.filter(|c| modified.is_some() && c.modified == modified && c.size == size)
+    {
+        debug!(target: "files", "Using cached docs for unmodified file {}", file.display());
+        c.report_warnings();
+        return Ok((c.source(file), Some(c.clone())));
     }
-}
 
-fn run() -> Result<(), SrcDocError> {
-    let args = Args::parse();
-    let destination = &args.dest;
-    if !destination.exists() {
-        return Err(SrcDocError::new(format!(
-            "The destination path `{}` does not exist.",
-            destination.display()
-        )));
+    let content = fs::read(file).map_err(|e| SrcDocError::from(e).with_file(file))?;
+    let hash = content_hash(&content);
+    if let Some(c) = cached.filter(|c| c.hash == hash) {
+        debug!(target: "files", "Using cached docs for unchanged file {}", file.display());
+        c.report_warnings();
+        let entry = CachedFile {
+            modified,
+            size,
+            ..c.clone()
+        };
+        return Ok((c.source(file), Some(entry)));
     }
 
-    let config = match &args.config {
-        Some(x) => SrcDocConfig::from(x)?,
+    let (source, warnings) = capture_warnings(|| read_comment_lines(config, file, &content));
+    let source = source?;
+    let mut includes: Vec<_> = source.docs.iter().flat_map(|d| &d.includes).collect();
+    includes.sort();
+    includes.dedup();
+    let entry = CachedFile {
+        modified,
+        size,
+        hash,
+        docs: source.docs.clone(),
+        skipped: source.skipped,
+        includes: (includes.into_iter())
+            .map(|p| (p.clone(), fs::read(p).map_or(0, |c| content_hash(&c))))
+            .collect(),
+        warnings,
+    };
+    return Ok((source, Some(entry)));
+}
+
+/// Reads piped source content; `--stdin-ext` stands in for the missing file name when
+/// looking up the comment syntax
+fn read_stdin_comments(args: &Args, config: &SrcDocConfig) -> Result<SourceFile, SrcDocError> {
+    let ext = match &args.stdin_ext {
+        Some(x) => x.trim_start_matches('.'),
         None => {
-            let default_config = destination.join(".simple-src-docs.config.toml");
-            if default_config.is_file() {
-                SrcDocConfig::from(default_config)?
-            } else {
-                SrcDocConfig::new()
-            }
+            return Err(SrcDocError::new(String::from(
+                "Reading from standard input (`-`) requires `--stdin-ext`.",
+            ))
+            .with_kind(ErrorKind::Usage))
         }
     };
+    let file = PathBuf::from(format!("<stdin>.{}", ext));
+    let mut content = Vec::new();
+    io::stdin().lock().read_to_end(&mut content)?;
+    return read_comment_lines(config, &file, &content);
+}
 
-    let mut all_docs: Vec<DocData> = Vec::new();
-    for s in &args.source {
-        for entry in WalkDir::new(s) {
-            let file_entry = entry?;
-            let file = file_entry.path();
-            if !file.is_file() {
-                continue;
-            }
-            read_comments(&args, &config, file, &mut all_docs)?;
+/// Decodes `content` with `encoding` (UTF-8 if not given), unless it starts with a byte
+/// order mark, which determines the encoding instead; also returns the encoding used if some
+/// characters could not be read
+fn decode_source(
+    content: &[u8],
+    encoding: Option<&'static Encoding>,
+) -> (String, Option<&'static Encoding>) {
+    let (text, used, had_errors) = encoding.unwrap_or(encoding_rs::UTF_8).decode(content);
+    return (text.into_owned(), had_errors.then_some(used));
+}
+
+/// How many bytes at the start of a file are checked for the NUL bytes that mark it as
+/// binary (as git does)
+const BINARY_CHECK_LEN: usize = 8000;
+
+/// Characters that don't show in the docs but keep tags from matching, such as an `@file`
+/// after a byte order mark: the byte order marks left within the text (the decoder removes
+/// the one at its start), zero width spaces and word joiners
+const INVISIBLE_CHARS: [char; 3] = ['\u{feff}', '\u{200b}', '\u{2060}'];
+
+fn read_comment_lines(
+    config: &SrcDocConfig,
+    file: &Path,
+    content: &[u8],
+) -> Result<SourceFile, SrcDocError> {
+    debug!(target: "files", "Reading file {}", file.display());
+    let mut source = SourceFile {
+        path: file.to_path_buf(),
+        docs: Vec::new(),
+        skipped: false,
+    };
+    let mut comment_configs = config.find_comment_configs(file);
+    let encoding = |configs: &[&CommentConfig]| configs.iter().find_map(|c| c.encoding);
+    // (UTF-16 text is full of NUL bytes)
+    let utf16 = [encoding_rs::UTF_16LE, encoding_rs::UTF_16BE];
+    let wide = Encoding::for_bom(content)
+        .map(|(e, _)| e)
+        .or(encoding(&comment_configs));
+    if !wide.is_some_and(|e| utf16.contains(&e))
+        && content.iter().take(BINARY_CHECK_LEN).any(|b| *b == 0)
+    {
+        debug!(target: "files", "Skipping binary file {}", file.display());
+        source.skipped = true;
+        return Ok(source);
+    }
+    let (mut text, mut invalid) = decode_source(content, encoding(&comment_configs));
+    if comment_configs.is_empty() {
+        comment_configs = config.find_shebang_configs(file, text.lines().next().unwrap_or(""));
+        if let Some(encoding) = encoding(&comment_configs) {
+            (text, invalid) = decode_source(content, Some(encoding));
         }
     }
-    all_docs.sort_by(|a, b| a.order.partial_cmp(&b.order).unwrap_or(Ordering::Less));
-    let mut docmap = config.apply(&all_docs.iter().map(|x| x).collect())?;
-
-    if args.verbose {
-        println!("Writing doc files:");
+    // files that none of the readers apply to don't have to be text
+    let read = !comment_configs.is_empty() || is_markdown_page(config, file) || is_notebook(file);
+    if let Some(used) = invalid.filter(|_| read) {
+        warn!(
+            file:% = file.display();
+            "File isn't valid {}, some characters could not be read", used.name()
+        );
     }
-    for (file, items) in docmap.iter_mut() {
-        if args.verbose {
-            println!(" - {}", file);
+    if config.newline.normalize {
+        // `lines` only splits at `\n` and `\r\n`, so lone `\r`s would end up in the docs
+        text = text.replace("\r\n", "\n").replace('\r', "\n");
+    }
+    if text.contains(INVISIBLE_CHARS) {
+        text = text.replace(INVISIBLE_CHARS, "");
+    }
+    if config.unicode.nfc {
+        text = text.nfc().collect();
+    }
+    if comment_configs.is_empty() && is_markdown_page(config, file) {
+        source.docs.extend(read_markdown_page(config, file, &text)?);
+        return Ok(source);
+    }
+    if comment_configs.is_empty() && is_notebook(file) {
+        source.docs = read_notebook(config, file, &text)?;
+        return Ok(source);
+    }
+    if comment_configs.is_empty() {
+        debug!(target: "files", "Skipping file without a matching extension");
+        source.skipped = true;
+    }
+    let lines: Vec<_> = text.lines().collect();
+    for c in &comment_configs {
+        let comments = Comments::new(text.lines().map(String::from), c);
+        let tag_rs = match c.tag_pattern.is_empty() {
+            true => vec![config.tag_pattern.as_ref().unwrap_or(&DEFAULT_TAG_PATTERN)],
+            false => c.tag_pattern.iter().collect(),
+        };
+        let mut docs = Vec::new();
+        for d in DocIterator::new(comments, file, tag_rs.clone(), config) {
+            let mut d = d?;
+            d.signature = c.find_signature(&lines, d.span);
+            docs.push(d);
         }
-        let path = destination.join(file);
-        if !path.starts_with(destination) {
-            return Err(SrcDocError::new(format!(
-                "File path `{}` is not a child of the destination path `{}`.",
-                path.display(),
-                destination.display()
-            )));
+        if let Some(inline) = &c.inline {
+            read_inline_comments(config, file, &lines, inline, &tag_rs, &mut docs)?;
         }
-        let dir = path.parent().unwrap();
-
-        fs::create_dir_all(dir)?;
-        let mut io = File::create(&path)?;
+        for d in docs {
+            trace!(target: "files", "Found comment with tags:");
+            for (k, v) in &d.tags {
+                trace!(target: "files", "@{}: {}", k, v);
+            }
+            source.docs.push(d);
+        }
+    }
+    // keep the blocks found by each comment syntax in the order they appear in the file
+    source.docs.sort_by_key(|d| d.line);
+    return Ok(source);
+}
 
-        items.sort_by(|a, b| a.0.partial_cmp(&b.0).unwrap_or(Ordering::Less));
-        for (_, body) in items {
-            write!(io, "{}", body)?;
+/// Adds the `inline` comments written after code on the same line (e.g. `int x; ///< doc`)
+/// in `lines` to `docs`, the blocks read from the same file with the same comment syntax
+fn read_inline_comments(
+    config: &SrcDocConfig,
+    file: &Path,
+    lines: &[&str],
+    inline: &InlineConfig,
+    tag_rs: &[&Regex],
+    docs: &mut Vec<DocData>,
+) -> Result<(), SrcDocError> {
+    let mut found = Vec::new();
+    for (i, line) in lines.iter().enumerate() {
+        let line_number = i + 1;
+        if docs
+            .iter()
+            .any(|d| d.span.0 <= line_number && line_number <= d.span.1)
+        {
+            continue;
+        }
+        let capture = inline.pattern.captures(line);
+        if let Some(text) = capture.as_ref().and_then(|c| c.get(1)) {
+            found.push((line_number, String::from(text.as_str().trim())));
+        }
+    }
+    for (line_number, text) in found {
+        if inline.attach {
+            let preceding = docs
+                .iter_mut()
+                .filter(|d| d.span.1 < line_number)
+                .max_by_key(|d| d.span.1);
+            if let Some(doc) = preceding {
+                doc.body.push_str(&text);
+                doc.body.push('\n');
+                continue;
+            }
+        }
+        let mut comments = Comments::new(std::iter::once(text), &WHOLE_LINES);
+        comments.line = line_number - 1;
+        for d in DocIterator::new(comments, file, tag_rs.to_vec(), config) {
+            docs.push(d?);
         }
     }
     return Ok(());
 }
 
-// Language Configuration //////////////////////////////////////////////////////////////////
+/// True for the markdown pages read whole by `read_markdown_page` (see `MarkdownConfig`)
+fn is_markdown_page(config: &SrcDocConfig, file: &Path) -> bool {
+    return config.markdown.passthrough
+        && file.extension().is_some_and(|e| {
+            return e.eq_ignore_ascii_case("md") || e.eq_ignore_ascii_case("markdown");
+        });
+}
 
-fn str_to_glob<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Glob<'static>, D::Error> {
-    let s: String = Deserialize::deserialize(deserializer)?;
-    return match Glob::new(&format!("(?i){}", s)) {
-        Ok(g) => Ok(g.into_owned()),
-        Err(e) => Err(serde::de::Error::custom(e)),
+/// Reads a hand-written markdown page as a single doc block, unless it has the `nodoc` tag;
+/// the keys of its YAML front matter (between `---` lines at the start of the file) are its
+/// tags
+fn read_markdown_page(
+    config: &SrcDocConfig,
+    file: &Path,
+    text: &str,
+) -> Result<Option<DocData>, SrcDocError> {
+    let mut lines = text.lines();
+    let mut front_matter = String::new();
+    let mut body_start = 1;
+    if text.lines().next().is_some_and(|l| l.trim_end() == "---") {
+        lines.next();
+        let mut closed = false;
+        for line in lines.by_ref() {
+            body_start += 1;
+            if line.trim_end() == "---" {
+                closed = true;
+                break;
+            }
+            front_matter.push_str(line);
+            front_matter.push('\n');
+        }
+        if !closed {
+            return Err(SrcDocError::new(String::from(
+                "The front matter is missing its closing `---`.",
+            ))
+            .with_file(file)
+            .with_line(1)
+            .with_kind(ErrorKind::Source));
+        }
+        body_start += 1;
     }
-}
+    let values: BTreeMap<String, serde_yaml::Value> = match front_matter.trim().is_empty() {
+        true => BTreeMap::new(),
+        false => serde_yaml::from_str(&front_matter).map_err(|e| {
+            return SrcDocError::new(format!("Invalid front matter: {}", e))
+                .with_file(file)
+                .with_line(1)
+                .with_kind(ErrorKind::Source);
+        })?,
+    };
 
-fn glob_to_str<S: serde::Serializer>(s: &Glob, serializer: S) -> Result<S::Ok, S::Error> {
-    return serializer.serialize_str(s.to_string().as_str());
+    let mut tags = BlockTags::default();
+    for (key, value) in values {
+        let tag = &config.tags.name(&key);
+        let value = match value {
+            serde_yaml::Value::String(x) => x,
+            serde_yaml::Value::Number(x) => x.to_string(),
+            serde_yaml::Value::Bool(x) => x.to_string(),
+            _ => {
+                warn!(
+                    file:% = file.display(), line = 1;
+                    "Ignoring the front matter key `{}`: only strings, numbers and booleans can \
+                     be tags", key
+                );
+                continue;
+            }
+        };
+        tags.add(&config.tags, tag, &value, file, 1)?;
+    }
+    if tags.nodoc {
+        return Ok(None);
+    }
+    tags.validate(&config.tags, file)?;
+
+    let mut body: String = lines.collect::<Vec<_>>().join("\n");
+    body.push('\n');
+    let end = text.lines().count();
+    return Ok(Some(tags.into_doc(
+        body,
+        file,
+        body_start,
+        (1, end),
+        Vec::new(),
+    )));
 }
 
-#[derive(Serialize, Deserialize, Clone)]
-struct CommentConfig {
-    #[serde(default = "zero")]
-    order: f64,
-    #[serde(deserialize_with = "str_to_glob", serialize_with = "glob_to_str")]
-    extension: Glob<'static>,
-    #[serde(with = "serde_regex")]
-    start: Option<Regex>,
-    #[serde(with = "serde_regex")]
-    each_line: Option<Regex>,
-    #[serde(with = "serde_regex")]
-    stop: Option<Regex>,
+/// True for Jupyter notebooks, which are read by `read_notebook`
+fn is_notebook(file: &Path) -> bool {
+    return file
+        .extension()
+        .is_some_and(|e| e.eq_ignore_ascii_case("ipynb"));
 }
 
-lazy_static! {
-    static ref DEFAULT_COMMENT_MAP: Vec<CommentConfig> = {
-        let mut m = Vec::new();
-        m.push(CommentConfig {
-            order: 0.0,
-            extension: Glob::new("(?i)*.{c,cpp,java,h,hpp,c++,h++,cxx,hxx,groovy,v,js,cs,ts,jsx,tsx,go,zig,kt,kts,d,swift,php,css,scala,dart,m}").unwrap(),
-            start: Some(Regex::new(r"^\s*/\*\*\s*$").unwrap()),
-            each_line: Some(Regex::new(r"^\s*\*\s?(.*)").unwrap()),
-            stop: Some(Regex::new(r"^\s*\*/+\s*").unwrap()),
-        });
-        m.push(CommentConfig {
-            order: 0.0,
-            extension: Glob::new("(?i)*.{rb,r,sh,pl,pm,jl,awk,nim,crystal,tcl}").unwrap(),
-            start: None,
-            each_line: Some(Regex::new(r"^\s*#\s?x(.*)$").unwrap()),
-            stop: None,
-        });
-        m.push(CommentConfig {
-            order: 1.0,
-            extension: Glob::new("(?i)*.{asm,s,clj,el,lisp,scm,ss,rkt}").unwrap(),
-            start: None,
-            each_line: Some(Regex::new(r"^\s*;\s?(.*)$").unwrap()),
-            stop: None,
-        });
-        m.push(CommentConfig {
-            order: 1.0,
-            extension: Glob::new("(?i)*.{vb,vba}").unwrap(),
-            start: None,
-            each_line: Some(Regex::new(r"^\s*'\s?(.*)$").unwrap()),
-            stop: None,
-        });
-        m.push(CommentConfig {
-            order: 1.0,
-            extension: Glob::new("(?i)*.{f,for,f90,f95,fortran}").unwrap(),
-            start: None,
-            each_line: Some(Regex::new(r"^\s*!\s?(.*)$").unwrap()),
-            stop: None,
-        });
-        m.push(CommentConfig {
-            order: 0.0,
-            extension: Glob::new("(?i)*.{lua,hs,elm,sql}").unwrap(),
-            start: None,
-            each_line: Some(Regex::new(r"^\s*--\s?(.*)$").unwrap()),
-            stop: None,
-        });
-        m.push(CommentConfig {
-            order: 0.0,
-            extension: Glob::new("(?i)*.{py,pyi}").unwrap(),
-            start: Some(Regex::new(r#"^\s*"""\s*$"#).unwrap()),
-            each_line: None,
-            stop: Some(Regex::new(r#"^\s*"""\s*$"#).unwrap()),
-        });
-        m.push(CommentConfig {
-            order: 0.0,
-            extension: Glob::new("(?i)*.rs").unwrap(),
-            start: None,
-            each_line: Some(Regex::new(r"^\s*///\s?(.*)$").unwrap()),
-            stop: None,
-        });
-        m.push(CommentConfig {
-            order: 0.0,
-            extension: Glob::new("(?i)*.jl").unwrap(),
-            start: Some(Regex::new(r"^\s*#=\s*$").unwrap()),
-            each_line: None,
-            stop: Some(Regex::new(r"^\s*=#\s*$").unwrap()),
-        });
+/// The cell tag that marks a notebook code cell whose comments are docs
+const NOTEBOOK_DOC_TAG: &str = "doc";
 
-        m
+lazy_static! {
+    /// reads every line given as one doc block, such as those of a markdown cell
+    static ref WHOLE_LINES: CommentConfig = CommentConfig {
+        each_line: Some(Regex::new(r"^(.*)$").unwrap()),
+        ..notebook_cell_config()
+    };
+    /// reads the `#` comments of a code cell
+    static ref NOTEBOOK_CODE_CELL: CommentConfig = CommentConfig {
+        each_line: Some(Regex::new(r"^\s*#(?:\s(.*)|([^!].*)|())$").unwrap()),
+        ..notebook_cell_config()
     };
 }
 
-// Templates ///////////////////////////////////////////////////////////////////////////////
-
-fn start_stop_match(comment: &Vec<CommentConfig>) -> Result<(), ValidationError> {
-    for c in comment {
-        if c.start.is_none() ^ c.stop.is_none() {
-            return Err(ValidationError::new(
-                "start and stop must both be present, or they must both be absent.",
-            ));
-        }
-    }
-    return Ok(());
+fn notebook_cell_config() -> CommentConfig {
+    return CommentConfig {
+        order: 0.0,
+        builtin: true,
+        extension: Glob::new("(?i)*.ipynb").unwrap(),
+        path: None,
+        start: None,
+        each_line: None,
+        stop: None,
+        tag_pattern: Vec::new(),
+        encoding: None,
+        signature: None,
+        nested_start: None,
+        inline: None,
+        merge: None,
+    };
 }
 
-#[derive(Deserialize, Validate)]
-struct SrcDocConfig {
-    header: ConfigHeader,
-    #[serde(default)]
-    template: Option<ConfigTemplates>,
-    #[serde(default)]
-    #[validate(custom(function = "start_stop_match"))]
-    comment: Option<Vec<CommentConfig>>,
+/// A Jupyter notebook, with the JSON of each cell's source as written, so that its lines can
+/// be found in the file
+#[derive(Deserialize)]
+struct Notebook<'a> {
+    #[serde(borrow, default)]
+    cells: Vec<NotebookCell<'a>>,
 }
 
 #[derive(Deserialize)]
-struct ConfigTemplates {
+struct NotebookCell<'a> {
     #[serde(default)]
-    foreach: Option<Vec<DocEachTemplate>>,
+    cell_type: String,
     #[serde(default)]
-    all: Option<Vec<DocAllTemplate>>,
+    metadata: serde_json::Value,
+    /// either a string or an array of lines
+    #[serde(borrow)]
+    source: Option<&'a serde_json::value::RawValue>,
 }
 
-impl SrcDocConfig {
-    fn new() -> SrcDocConfig {
-        return SrcDocConfig {
-            header: ConfigHeader {
-                version: Version::parse("0.2.1").unwrap(),
-            },
-            template: None,
-            comment: Some(DEFAULT_COMMENT_MAP.clone()),
-        };
-    }
-
-    fn from<T: AsRef<Path>>(path: T) -> Result<SrcDocConfig, SrcDocError> {
-        let str = fs::read_to_string(&path)?;
-        let mut result = toml::from_str::<SrcDocConfig>(&str)?;
-        let comment = if let Some(mut comment_map) = result.comment {
-            for c in DEFAULT_COMMENT_MAP.iter() {
-                comment_map.push(c.clone());
+/// Extracts the docs of a Jupyter notebook: each markdown cell is a doc block, as are the
+/// `#` comments of code cells tagged `doc`
+fn read_notebook(
+    config: &SrcDocConfig,
+    file: &Path,
+    text: &str,
+) -> Result<Vec<DocData>, SrcDocError> {
+    let invalid = |e: serde_json::Error| {
+        return SrcDocError::new(format!("Invalid notebook: {}", e))
+            .with_file(file)
+            .with_line(e.line())
+            .with_kind(ErrorKind::Source);
+    };
+    let notebook: Notebook = serde_json::from_str(text).map_err(invalid)?;
+    let breaks: Vec<usize> = text.match_indices('\n').map(|(i, _)| i).collect();
+    let line_of = |json: &serde_json::value::RawValue| {
+        let offset = json.get().as_ptr() as usize - text.as_ptr() as usize;
+        return breaks.partition_point(|b| *b < offset) + 1;
+    };
+    let tag_rs = vec![config.tag_pattern.as_ref().unwrap_or(&DEFAULT_TAG_PATTERN)];
+    let mut docs = Vec::new();
+    for cell in notebook.cells {
+        let cell_config: &CommentConfig = match cell.cell_type.as_str() {
+            "markdown" => &WHOLE_LINES,
+            "code" => {
+                let tags = cell.metadata["tags"].as_array().into_iter().flatten();
+                if !tags.into_iter().any(|t| t == NOTEBOOK_DOC_TAG) {
+                    continue;
+                }
+                &NOTEBOOK_CODE_CELL
             }
-            Some(comment_map)
-        } else {
-            Some(DEFAULT_COMMENT_MAP.clone())
+            _ => continue,
         };
-        result.comment = comment;
-        return Ok(result);
-    }
-
-    fn find_comment_config(&self, file: &Path) -> Option<&CommentConfig> {
-        return self.comment.as_ref()?.iter().find_map(|c| {
-            if c.extension.is_match(file) || c.extension.is_match(file.file_name()?) {
-                return Some(c);
+        let Some(source) = cell.source else {
+            continue;
+        };
+        // each part of the source, along with the line it is written on
+        let parts: Vec<(usize, String)> =
+            match serde_json::from_str::<Vec<&serde_json::value::RawValue>>(source.get()) {
+                Ok(lines) => (lines.into_iter())
+                    .filter_map(|l| Some((line_of(l), serde_json::from_str(l.get()).ok()?)))
+                    .collect(),
+                Err(_) => {
+                    let part = serde_json::from_str(source.get()).unwrap_or_default();
+                    vec![(line_of(source), part)]
+                }
+            };
+        // a line of the source is on the line of the part that it starts in
+        let mut lines: Vec<(usize, String)> = Vec::new();
+        let mut partial: Option<(usize, String)> = None;
+        for (line, part) in parts {
+            for piece in part.split_inclusive('\n') {
+                let (start, mut current) = partial.take().unwrap_or((line, String::new()));
+                current.push_str(piece);
+                match current.ends_with('\n') {
+                    true => lines.push((start, current)),
+                    false => partial = Some((start, current)),
+                }
             }
+        }
+        lines.extend(partial);
+        let (numbers, lines): (VecDeque<usize>, Vec<String>) = (lines.into_iter())
+            .map(|(n, l)| {
+                (
+                    n,
+                    String::from(l.trim_end_matches('\n').trim_end_matches('\r')),
+                )
+            })
+            .unzip();
 
-            return None;
-        });
+        let mut comments = Comments::new(lines.into_iter(), cell_config);
+        comments.numbers = numbers;
+        for d in DocIterator::new(comments, file, tag_rs.clone(), config) {
+            docs.push(d?);
+        }
     }
+    return Ok(docs);
 }
 
-fn valid_version(v: &Version) -> Result<(), ValidationError> {
-    // we're on version 0.2.1: any files semver compatible with 0.2 are fine
-    if VersionReq::parse("0.2").unwrap().matches(v) {
-        return Ok(());
-    } else {
-        return Err(ValidationError::new(
-            "File version incompatible with semver 0.2",
-        ));
-    }
+fn compile_globs(flag: &str, patterns: &[String]) -> Result<Vec<Glob<'static>>, SrcDocError> {
+    return patterns
+        .iter()
+        .map(|p| match Glob::new(p) {
+            Ok(g) => Ok(g.into_owned()),
+            Err(e) => Err(
+                SrcDocError::new(format!("Invalid {} pattern `{}`: {}", flag, p, e))
+                    .with_kind(ErrorKind::Usage),
+            ),
+        })
+        .collect();
 }
 
-#[derive(Deserialize, Validate)]
-struct ConfigHeader {
-    #[validate(custom(function = "valid_version"))]
-    version: Version,
+/// True if any of `globs` matches `path` relative to `root`, or the name of `path`
+fn matches_any(globs: &[Glob], root: &Path, path: &Path) -> bool {
+    let relative = path.strip_prefix(root).unwrap_or(path);
+    return globs
+        .iter()
+        .any(|g| g.is_match(relative) || path.file_name().is_some_and(|name| g.is_match(name)));
 }
 
-fn zero() -> f64 {
-    return 0.0;
+/// True if the walker stopped at a symbolic link pointing to one of its parent directories
+fn is_loop(e: &ignore::Error) -> bool {
+    return match e {
+        ignore::Error::Loop { .. } => true,
+        ignore::Error::WithPath { err, .. } => is_loop(err),
+        ignore::Error::WithDepth { err, .. } => is_loop(err),
+        ignore::Error::WithLineNumber { err, .. } => is_loop(err),
+        _ => false,
+    };
 }
 
-fn left_zero() -> Either<f64, String> {
-    return Left(0.0);
+enum SourceInput {
+    Stdin,
+    /// a file, along with the directory it was found in (see `DocData::source_file`)
+    File(PathBuf, PathBuf),
 }
 
-#[derive(Deserialize)]
-struct DocEachTemplate {
-    tags: Vec<String>,
-    file: String,
-    #[serde(with = "either::serde_untagged", default = "left_zero")]
-    order: Either<f64, String>,
-    output: String,
+/// Walks each of the `sources` and extracts the docs of every file found; files are read in
+/// parallel (see `--jobs`) but are returned in traversal order
+fn read_sources(
+    args: &Args,
+    config: &SrcDocConfig,
+    sources: &[PathBuf],
+) -> Result<Vec<SourceFile>, SrcDocError> {
+    let mut exclude = compile_globs("--exclude", &args.exclude)?;
+    exclude.extend(compile_globs("`ignore`", &config.ignore)?);
+    let include = compile_globs("--include", &args.include)?;
+    let mut inputs = Vec::new();
+    if let Some(list) = &args.files_from {
+        if list.as_os_str() == "-" && sources.iter().any(|s| s.as_os_str() == "-") {
+            return Err(SrcDocError::new(String::from(
+                "Standard input can't be used for both `--files-from` and `<SOURCE>`.",
+            ))
+            .with_kind(ErrorKind::Usage));
+        }
+        for file in read_file_list(list)? {
+            if !file.is_file() {
+                info!(target: "files", "Skipping listed path {}: not a file", file.display());
+                continue;
+            }
+            inputs.push(SourceInput::File(file, PathBuf::new()));
+        }
+    }
+    for s in sources {
+        if s.as_os_str() == "-" {
+            inputs.push(SourceInput::Stdin);
+            continue;
+        }
+        let root = s.clone();
+        // a file given as a source is relative to its own directory
+        let source_root = match s.is_file() {
+            true => s.parent().map(Path::to_path_buf).unwrap_or_default(),
+            false => s.clone(),
+        };
+        let root_exclude = exclude.clone();
+        let walker = WalkBuilder::new(s)
+            .standard_filters(false)
+            .git_ignore(args.respect_gitignore)
+            .git_global(args.respect_gitignore)
+            .git_exclude(args.respect_gitignore)
+            .parents(args.respect_gitignore)
+            .require_git(false)
+            .follow_links(args.follow_symlinks)
+            .max_depth(args.max_depth)
+            .filter_entry(move |entry| {
+                return entry.depth() == 0 || !matches_any(&root_exclude, &root, entry.path());
+            })
+            .build();
+        for entry in walker {
+            let file_entry = match entry {
+                Ok(x) => x,
+                Err(e) if is_loop(&e) => {
+                    warn!("Skipping symbolic link cycle: {}", e);
+                    continue;
+                }
+                Err(e) => return Err(e.into()),
+            };
+            let file = file_entry.path();
+            if !file.is_file() {
+                continue;
+            }
+            if !include.is_empty() && !matches_any(&include, s, file) {
+                continue;
+            }
+            inputs.push(SourceInput::File(file.to_path_buf(), source_root.clone()));
+        }
+    }
+
+    let cache_path = cache_path(&args.dest);
+    let cache = if args.cache {
+        DocCache::load(&cache_path, parse_fingerprint(config))
+    } else {
+        DocCache::empty(parse_fingerprint(config))
+    };
+
+    let max_size = args.max_file_size.or(config.max_file_size);
+    let pool = rayon::ThreadPoolBuilder::new()
+        .num_threads(args.jobs.unwrap_or(0))
+        .build()
+        .map_err(|e| SrcDocError::new(format!("Error starting worker threads: {}", e)))?;
+    let progress = progress_bar(args, inputs.len(), "Reading");
+    let results: Vec<(SourceFile, Option<CachedFile>)> = pool.install(|| {
+        return inputs
+            .par_iter()
+            .inspect(|_| progress.inc(1))
+            .map(|input| {
+                let (mut source, entry, relative) = match input {
+                    SourceInput::Stdin => (read_stdin_comments(args, config)?, None, None),
+                    SourceInput::File(file, root) => {
+                        let (source, entry) = read_comments(config, file, &cache, max_size)?;
+                        (source, entry, Some(file.strip_prefix(root).unwrap_or(file)))
+                    }
+                };
+                // looked up once per file, since it resolves the file's path
+                let section = config.find_source_config(&source.path);
+                for doc in &mut source.docs {
+                    if let Some(relative) = relative {
+                        doc.source_file = relative.to_path_buf();
+                    }
+                    doc.section = section;
+                }
+                return Ok((source, entry));
+            })
+            .collect::<Result<_, SrcDocError>>();
+    })?;
+    progress.finish_and_clear();
+
+    record(|summary| {
+        summary.files_scanned += results.len();
+        summary.files_skipped += results.iter().filter(|(f, _)| f.skipped).count();
+        summary.docs_extracted += results.iter().map(|(f, _)| f.docs.len()).sum::<usize>();
+    });
+
+    let mut new_cache = DocCache::empty(cache.fingerprint);
+    let mut files = Vec::new();
+    for (source, entry) in results {
+        if let Some(e) = entry {
+            new_cache.files.insert(source.path.clone(), e);
+        }
+        files.push(source);
+    }
+    // a strict run stops because of these warnings, so it isn't worth keeping
+    let failed = (args.strict || config.strict) && WARNINGS.load(atomic::Ordering::Relaxed) > 0;
+    if args.cache && !failed {
+        new_cache.save(&cache_path)?;
+    }
+    return Ok(files);
 }
 
-#[derive(Deserialize)]
-struct DocAllTemplate {
-    file: String,
-    tags: Vec<String>,
-    #[serde(default = "zero")]
-    order: f64,
-    output: String,
+/// Reads the newline separated paths listed for `--files-from`, ignoring empty lines
+fn read_file_list(list: &Path) -> Result<Vec<PathBuf>, SrcDocError> {
+    let content = if list.as_os_str() == "-" {
+        io::read_to_string(io::stdin())?
+    } else {
+        fs::read_to_string(list).map_err(|e| {
+            return SrcDocError::new(format!("Error reading file list: {}", e))
+                .with_file(list)
+                .with_kind(ErrorKind::Io);
+        })?
+    };
+    return Ok(content
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty())
+        .map(PathBuf::from)
+        .collect());
 }
 
-enum TemplateError {
-    Mustache(mustache::Error),
-    Parse(ParseFloatError),
+fn load_config(args: &Args, sources: &[PathBuf]) -> Result<SrcDocConfig, SrcDocError> {
+    let path = find_config(args, sources);
+    let mut config = match path.is_none() && args.set.is_empty() {
+        true => SrcDocConfig::new(),
+        false => SrcDocConfig::from(path.as_deref(), &args.set)?,
+    };
+    config.define.extend(args.define.iter().cloned());
+    return Ok(config);
 }
 
-impl From<TemplateError> for SrcDocError {
-    fn from(value: TemplateError) -> Self {
-        return match value {
-            TemplateError::Parse(e) => SrcDocError::new(format!("Error parsing @order {}", e)),
-            TemplateError::Mustache(e) => SrcDocError::new(format!("Template parsing error {}", e)),
+/// The syntax of a config file, determined by its extension
+#[derive(Clone, Copy, PartialEq)]
+enum ConfigFormat {
+    Toml,
+    Yaml,
+    Json,
+}
+
+impl ConfigFormat {
+    fn of(path: &Path) -> ConfigFormat {
+        let ext = path.extension().map(|e| e.to_string_lossy().to_lowercase());
+        return match ext.as_deref() {
+            Some("yaml" | "yml") => ConfigFormat::Yaml,
+            Some("json") => ConfigFormat::Json,
+            _ => ConfigFormat::Toml,
         };
     }
-}
 
-impl From<mustache::Error> for TemplateError {
-    fn from(value: mustache::Error) -> Self {
-        return TemplateError::Mustache(value);
+    /// Parses `str` into the equivalent TOML value, so that all formats share the same
+    /// handling of includes, overrides and deserialization
+    fn parse(self, str: &str) -> Result<toml::Value, SrcDocError> {
+        let config_error = |e: String| {
+            return SrcDocError::new(format!("Config Error: {}", e)).with_kind(ErrorKind::Config);
+        };
+        return match self {
+            ConfigFormat::Toml => Ok(toml::from_str::<toml::Value>(str)?),
+            ConfigFormat::Yaml => {
+                serde_yaml::from_str(str).map_err(|e| config_error(e.to_string()))
+            }
+            ConfigFormat::Json => {
+                serde_json::from_str(str).map_err(|e| config_error(e.to_string()))
+            }
+        };
     }
-}
 
-impl From<ParseFloatError> for TemplateError {
-    fn from(value: ParseFloatError) -> Self {
-        return TemplateError::Parse(value);
+    /// Writes `value` back out in this format
+    fn serialize(self, value: &toml::Value) -> Result<String, SrcDocError> {
+        let config_error = |e: String| {
+            return SrcDocError::new(format!("Config Error: {}", e)).with_kind(ErrorKind::Config);
+        };
+        return match self {
+            ConfigFormat::Toml => toml::to_string(value).map_err(|e| config_error(e.to_string())),
+            ConfigFormat::Yaml => {
+                serde_yaml::to_string(value).map_err(|e| config_error(e.to_string()))
+            }
+            ConfigFormat::Json => serde_json::to_string_pretty(value)
+                .map(|x| x + "\n")
+                .map_err(|e| config_error(e.to_string())),
+        };
     }
 }
 
-fn parse_order(order_str: &str) -> f64 {
-    return match order_str.trim().parse() {
-        Ok(x) => x,
-        Err(e) => {
-            eprintln!("Error while evaluating @order {order_str}: {e}");
-            0.0
-        }
+/// Merges the files listed by the `include` key of `config` (read from `path`) into it;
+/// paths are relative to the including file and `stack` holds the files being included, to
+/// detect cycles
+fn include_configs(
+    config: &mut toml::Value,
+    path: &Path,
+    stack: &mut Vec<PathBuf>,
+) -> Result<(), SrcDocError> {
+    let Some(include) = config.as_table_mut().and_then(|t| t.remove("include")) else {
+        return Ok(());
     };
+    let files = include.try_into::<Vec<PathBuf>>().map_err(|_| {
+        let msg = String::from("Config Error: `include` should be an array of file paths");
+        return SrcDocError::new(msg)
+            .with_file(path)
+            .with_kind(ErrorKind::Config);
+    })?;
+    let dir = path.parent().unwrap_or(Path::new(""));
+    for file in files {
+        let file = dir.join(file);
+        let canonical =
+            fs::canonicalize(&file).map_err(|e| SrcDocError::from(e).with_file(&file))?;
+        if stack.contains(&canonical) {
+            let msg = String::from("Config Error: the file includes itself");
+            return Err(SrcDocError::new(msg)
+                .with_file(&file)
+                .with_kind(ErrorKind::Config));
+        }
+        let text = fs::read_to_string(&file).map_err(|e| SrcDocError::from(e).with_file(&file))?;
+        check_config_file(&text, &file)?;
+        let mut included = ConfigFormat::of(&file)
+            .parse(&text)
+            .map_err(|e| e.with_file(&file))?;
+        stack.push(canonical);
+        include_configs(&mut included, &file, stack)?;
+        stack.pop();
+        rebase_template_files(&mut included, file.parent().unwrap_or(Path::new("")));
+        rebase_section_paths(&mut included, file.parent().unwrap_or(Path::new("")));
+        merge_configs(config, included);
+    }
+    return Ok(());
 }
 
-impl DocEachTemplate {
-    fn apply<'a>(
-        &self,
-        docs: &Vec<&'a DocData>,
-        result: &mut HashMap<String, Vec<(f64, String)>>,
-    ) -> Result<(), TemplateError> {
-        for doc in docs {
-            if !self.tags.iter().all(|tag| doc.tags.contains_key(tag)) {
-                continue;
-            }
+/// Reads the TOML config file `text` (from `path`) on its own, before it is merged with the
+/// files it includes or extends and any `--set` overrides, so that its problems are reported
+/// where they are in the file; the settings it leaves out, which the other files can give,
+/// are checked once they are merged
+fn check_config_file(text: &str, path: &Path) -> Result<(), SrcDocError> {
+    if ConfigFormat::of(path) != ConfigFormat::Toml {
+        return Ok(());
+    }
+    let Err(e) = toml::from_str::<SrcDocConfig>(text) else {
+        return Ok(());
+    };
+    if e.message().starts_with("missing field") {
+        return Ok(());
+    }
+    let line = e.span().map(|s| text[..s.start].matches('\n').count() + 1);
+    let error = SrcDocError::from(e).with_file(path);
+    return Err(match line {
+        Some(line) => error.with_line(line),
+        None => error,
+    });
+}
 
-            let mut builder = MapBuilder::new();
-            for (key, val) in &doc.tags {
-                builder = builder.insert_str(key, val);
+/// The config file at `path` and the (canonical paths of the) files it includes or extends,
+/// as far as they can be read
+fn config_files(path: &Path) -> Vec<PathBuf> {
+    let mut files = vec![path.to_path_buf()];
+    let mut i = 0;
+    while i < files.len() {
+        let file = files[i].clone();
+        i += 1;
+        let Ok(text) = fs::read_to_string(&file) else {
+            continue;
+        };
+        let Ok(config) = ConfigFormat::of(&file).parse(&text) else {
+            continue;
+        };
+        let include = config.get("include").and_then(|x| x.as_array());
+        let dir = file.parent().unwrap_or(Path::new(""));
+        let parent = extends(&config).and_then(|x| x.as_str());
+        let included = include.into_iter().flatten().filter_map(|x| x.as_str());
+        for included in included.chain(parent) {
+            let included = dir.join(included);
+            let included = fs::canonicalize(&included).unwrap_or(included);
+            if !files.contains(&included) {
+                files.push(included);
             }
-            builder = builder.insert_str("__body__", &doc.body);
-            let data = builder.build();
-
-            let file: String = mustache::compile_str(&self.file)?.render_data_to_string(&data)?;
-            let order: f64 = match &self.order {
-                Left(n) => *n,
-                Right(str) => {
-                    parse_order(&mustache::compile_str(&str)?.render_data_to_string(&data)?)
-                }
-            };
-            let body: String = mustache::compile_str(&self.output)?.render_data_to_string(&data)?;
-            let items = result.entry(file).or_insert(Vec::new());
-            items.push((order, body));
         }
-        return Ok(());
     }
+    return files;
 }
 
-impl DocAllTemplate {
-    fn apply<'a>(
-        &self,
-        docs: &Vec<&'a DocData>,
-        result: &mut HashMap<String, Vec<(f64, String)>>,
-    ) -> Result<(), TemplateError> {
-        let mut builder = MapBuilder::new();
-        builder = builder.insert_vec("items", |mut builder| {
-            for s in docs {
-                if !self.tags.iter().all(|tag| s.tags.contains_key(tag)) {
-                    continue;
-                }
-                builder = builder.push_map(|mut map_builder| {
-                    for (k, v) in &s.tags {
-                        map_builder = map_builder.insert_str(k, v);
-                    }
-                    map_builder = map_builder.insert_str("__body__", &s.body);
-                    return map_builder;
-                });
-            }
-            return builder;
-        });
+/// The parent config named by `header.extends`
+fn extends(config: &toml::Value) -> Option<&toml::Value> {
+    return config.get("header").and_then(|h| h.get("extends"));
+}
 
-        let data = builder.build();
-        let body: String = mustache::compile_str(&self.output)?.render_data_to_string(&data)?;
-        let items = result.entry(self.file.clone()).or_default();
-        items.push((self.order, body));
+/// Merges `config` (read from `path`) over the config file named by its `header.extends`,
+/// if any; `stack` holds the files being extended, to catch cycles
+fn extend_config(
+    config: &mut toml::Value,
+    path: &Path,
+    stack: &mut Vec<PathBuf>,
+) -> Result<(), SrcDocError> {
+    let header = config.get_mut("header").and_then(|h| h.as_table_mut());
+    let Some(extends) = header.and_then(|h| h.remove("extends")) else {
         return Ok(());
+    };
+    let Some(file) = extends.as_str() else {
+        let msg = String::from("Config Error: `header.extends` should be a file path");
+        return Err(SrcDocError::new(msg)
+            .with_file(path)
+            .with_kind(ErrorKind::Config));
+    };
+    let file = path.parent().unwrap_or(Path::new("")).join(file);
+    let canonical = fs::canonicalize(&file).map_err(|e| SrcDocError::from(e).with_file(&file))?;
+    if stack.contains(&canonical) {
+        let msg = String::from("Config Error: the file extends itself");
+        return Err(SrcDocError::new(msg)
+            .with_file(&file)
+            .with_kind(ErrorKind::Config));
     }
+    let text = fs::read_to_string(&file).map_err(|e| SrcDocError::from(e).with_file(&file))?;
+    check_config_file(&text, &file)?;
+    let mut parent = ConfigFormat::of(&file)
+        .parse(&text)
+        .map_err(|e| e.with_file(&file))?;
+    stack.push(canonical);
+    include_configs(&mut parent, &file, stack)?;
+    extend_config(&mut parent, &file, stack)?;
+    stack.pop();
+    rebase_template_files(&mut parent, file.parent().unwrap_or(Path::new("")));
+    rebase_section_paths(&mut parent, file.parent().unwrap_or(Path::new("")));
+    let child = std::mem::replace(config, parent);
+    override_config(config, child);
+    return Ok(());
 }
 
-impl SrcDocConfig {
-    fn apply<'a>(
-        &self,
-        data: &Vec<&'a DocData>,
-    ) -> Result<HashMap<String, Vec<(f64, String)>>, TemplateError> {
-        let mut results = HashMap::new();
-        if let Some(templates) = &self.template {
-            if let Some(each_templates) = &templates.foreach {
-                for each_template in each_templates {
-                    each_template.apply(data, &mut results)?;
-                }
+/// Merges `child` over `config`: tables are merged and any other setting of `child`
+/// (including arrays) replaces the one in `config`
+fn override_config(config: &mut toml::Value, child: toml::Value) {
+    let (Some(table), toml::Value::Table(child)) = (config.as_table_mut(), child) else {
+        return;
+    };
+    for (key, value) in child {
+        match (table.get_mut(&key), value) {
+            (Some(existing @ toml::Value::Table(_)), value @ toml::Value::Table(_)) => {
+                override_config(existing, value)
             }
-
-            if let Some(all_templates) = &templates.all {
-                for all_template in all_templates {
-                    all_template.apply(data, &mut results)?;
-                }
+            (_, value) => {
+                table.insert(key, value);
             }
+        }
+    }
+}
 
-            for doc in data {
-                if let Some(file) = doc.tags.get("file") {
-                    let order = doc.order;
-                    let items = results.entry(file.clone()).or_default();
-                    items.push((order, doc.body.clone()));
-                }
+/// Merges `other` into `config`: tables are merged, arrays (such as `comment` entries and
+/// templates) are appended to, and any other setting already in `config` is kept
+fn merge_configs(config: &mut toml::Value, other: toml::Value) {
+    let (Some(table), toml::Value::Table(other)) = (config.as_table_mut(), other) else {
+        return;
+    };
+    for (key, value) in other {
+        match (table.get_mut(&key), value) {
+            (None, value) => {
+                table.insert(key, value);
+            }
+            (Some(toml::Value::Array(array)), toml::Value::Array(values)) => array.extend(values),
+            (Some(existing @ toml::Value::Table(_)), value @ toml::Value::Table(_)) => {
+                merge_configs(existing, value)
             }
+            _ => (),
         }
-
-        return Ok(results);
     }
 }
 
-// Comments ////////////////////////////////////////////////////////////////////////////////
-
-struct Comments<'a, T: Iterator<Item = String>> {
-    lines: T,
-    in_comment: bool,
-    config: &'a CommentConfig,
-}
+/// The config file to use: the one given by `--config`, `<DEST>/.simple-src-docs.config.toml`
+/// or else the first `.simple-src-docs.config.toml` found in a source directory or one of
+/// its parents
+fn find_config(args: &Args, sources: &[PathBuf]) -> Option<PathBuf> {
+    if let Some(x) = &args.config {
+        return Some(x.clone());
+    }
+    let default_config = default_config_path(&args.dest);
+    if default_config.is_file() {
+        return Some(default_config);
+    }
 
-impl<'a, T: Iterator<Item = String>> Comments<'a, T> {
-    fn new(lines: T, config: &'a CommentConfig) -> Comments<'a, T> {
-        return Comments {
-            lines,
-            in_comment: false,
-            config,
+    let mut found: Option<PathBuf> = None;
+    for source in sources.iter().filter(|s| s.as_os_str() != "-") {
+        let Ok(dir) = fs::canonicalize(source) else {
+            continue;
         };
+        let config = dir
+            .ancestors()
+            .map(default_config_path)
+            .find(|config| config.is_file());
+        match (&found, config) {
+            (None, Some(config)) => {
+                debug!("Using config file {}", config.display());
+                found = Some(config);
+            }
+            (Some(first), Some(config)) if *first != config => {
+                warn!(
+                    "Ignoring config file {} found for `{}`, using {}",
+                    config.display(),
+                    source.display(),
+                    first.display()
+                );
+            }
+            _ => (),
+        }
     }
+    return found;
 }
 
-#[derive(Debug)]
-struct CommentResult {
-    value: String,
-    last: bool,
-}
-
-impl<'a, T: Iterator<Item = String>> Iterator for Comments<'a, T> {
-    type Item = CommentResult;
-    fn next(&mut self) -> Option<Self::Item> {
-        let value = match self.lines.next() {
-            None if self.in_comment => return Some(CommentResult {
-                value: String::new(),
-                last: true,
-            }),
-            None => return None,
-            Some(x) => x,
+/// Applies each `--set` override to `config`, creating any tables (or arrays) along the path
+/// that don't exist yet; an array index equal to the array's length appends a new table
+fn apply_overrides(config: &mut toml::Value, overrides: &[String]) -> Result<(), SrcDocError> {
+    for setting in overrides {
+        let usage_error = |msg: &str| {
+            let msg = format!("Invalid `--set {}`: {}", setting, msg);
+            return SrcDocError::new(msg).with_kind(ErrorKind::Usage);
+        };
+        let Some((key, value)) = setting.split_once('=') else {
+            return Err(usage_error("expected `KEY=VALUE`"));
+        };
+        let value = value.trim();
+        let value = match toml::from_str::<toml::Table>(&format!("value = {}", value)) {
+            Ok(mut table) => table.remove("value").unwrap(),
+            Err(_) => toml::Value::String(String::from(value)),
         };
 
-        if self.config.start.is_none() {
-            // single line comment syntax
-            let maybe_cap = self.config.each_line.as_ref().unwrap().captures(value.as_str());
-            if let Some(capture) = maybe_cap {
-                self.in_comment = true;
-                if let Some(cap_match) = capture.get(1) {
-                    return Some(CommentResult {
-                        value: String::from(cap_match.as_str()),
-                        last: false,
-                    });
-                }
-            } else if self.in_comment {
-                self.in_comment = false;
-                return Some(CommentResult {
-                    value: String::new(),
-                    last: true,
-                });
-            }
-        } else {
-            // multiline comment syntax
-            // validated invariant: if `start` is set, then `stop` is set
-            let start_p = self.config.start.as_ref().unwrap();
-            let end_p = self.config.stop.as_ref().unwrap();
-            if !self.in_comment && start_p.is_match(&value) {
-                self.in_comment = true;
-                return self.next();
-            } else if self.in_comment && end_p.is_match(&value) {
-                let result = Some(CommentResult {
-                    value: String::new(),
-                    last: true,
-                });
-                self.in_comment = false;
-                return result;
+        let path: Vec<&str> = key.trim().split('.').collect();
+        let mut node = &mut *config;
+        for (i, part) in path.iter().enumerate() {
+            if part.is_empty() {
+                return Err(usage_error("expected a dotted path to a setting"));
             }
-            if self.in_comment {
-                let each_line_r = match self.config.each_line.as_ref() {
-                    Some(x) => x,
-                    None => &Regex::new(r"\s*(.*)").unwrap(),
-                };
-                let maybe_cap = each_line_r.captures(&value);
-                if let Some(capture) = maybe_cap {
-                    if let Some(cap_match) = capture.get(1) {
-                        return Some(CommentResult {
-                            value: String::from(cap_match.as_str()),
-                            last: false,
-                        });
+            let set = i + 1 == path.len();
+            node = match node {
+                toml::Value::Table(table) => {
+                    if set {
+                        table.insert(part.to_string(), value);
+                        break;
                     }
+                    // a missing setting followed by an index is an array
+                    let child = if path[i + 1].parse::<usize>().is_ok() {
+                        toml::Value::Array(Vec::new())
+                    } else {
+                        toml::Value::Table(toml::Table::new())
+                    };
+                    table.entry(part.to_string()).or_insert(child)
                 }
-                return Some(CommentResult { value, last: false });
-            }
+                toml::Value::Array(array) => {
+                    let index = match part.parse::<usize>() {
+                        Ok(x) if x <= array.len() => x,
+                        _ => {
+                            let max = array.len();
+                            let msg = format!("`{}` should be an index from 0 to {}", part, max);
+                            return Err(usage_error(&msg));
+                        }
+                    };
+                    if index == array.len() {
+                        array.push(toml::Value::Table(toml::Table::new()));
+                    }
+                    if set {
+                        array[index] = value;
+                        break;
+                    }
+                    &mut array[index]
+                }
+                _ => {
+                    let parent = path[..i].join(".");
+                    let msg = format!("`{}` is not a table or an array", parent);
+                    return Err(usage_error(&msg));
+                }
+            };
         }
-        return self.next();
     }
+    return Ok(());
 }
 
-// Parsed Docs /////////////////////////////////////////////////////////////////////////////
+// Cache ///////////////////////////////////////////////////////////////////////////////////
 
-struct DocIterator<'a, T: Iterator<Item = String>> {
-    comments: Comments<'a, T>,
+fn cache_path(destination: &Path) -> PathBuf {
+    return destination.join(".simple-src-docs.cache");
 }
 
-#[derive(Debug)]
-struct DocData {
-    tags: HashMap<String, String>,
-    order: f64,
-    body: String,
+/// The docs extracted from each source file during the last run (see `--cache`)
+#[derive(Serialize, Deserialize)]
+struct DocCache {
+    version: String,
+    /// identifies the settings used to parse the cached files
+    fingerprint: u64,
+    files: HashMap<PathBuf, CachedFile>,
 }
 
-impl<'a, T: Iterator<Item = String>> DocIterator<'a, T> {
-    fn new(comments: Comments<'a, T>) -> DocIterator<'a, T> {
-        return DocIterator { comments };
-    }
+#[derive(Serialize, Deserialize, Clone)]
+struct CachedFile {
+    modified: Option<SystemTime>,
+    size: u64,
+    hash: u64,
+    docs: Vec<DocData>,
+    skipped: bool,
+    /// the files spliced into the docs by `@include`, with the hash of their content
+    #[serde(default)]
+    includes: Vec<(PathBuf, u64)>,
+    /// the warnings reported while parsing the file, reported again when the cached docs
+    /// are used
+    #[serde(default)]
+    warnings: Vec<CachedWarning>,
 }
 
-impl<'a, T: Iterator<Item = String>> Iterator for DocIterator<'a, T> {
-    type Item = DocData;
-    fn next(&mut self) -> Option<DocData> {
-        let tag_r: Regex = Regex::new(r".*@(?<tag>\S+)\s+(?<value>.*)").unwrap();
-        let mut body = String::new();
-        let mut tags = HashMap::new();
-        let mut available_data = false;
-        let mut order = 0.0;
-        let mut last_tag: Option<String> = None;
+#[derive(Serialize, Deserialize, Clone)]
+struct CachedWarning {
+    target: String,
+    message: String,
+    file: Option<String>,
+    line: Option<u64>,
+}
 
-        for comment in &mut self.comments {
-            if comment.last {
-                break;
+impl CachedFile {
+    /// True if the files spliced in by `@include` are as they were when cached
+    fn includes_unchanged(&self) -> bool {
+        return (self.includes.iter())
+            .all(|(path, hash)| fs::read(path).is_ok_and(|c| content_hash(&c) == *hash));
+    }
+
+    fn report_warnings(&self) {
+        for w in &self.warnings {
+            let target = w.target.as_str();
+            match (&w.file, w.line) {
+                (Some(file), Some(line)) => warn!(target: target, file, line; "{}", w.message),
+                (Some(file), None) => warn!(target: target, file; "{}", w.message),
+                _ => warn!(target: target, "{}", w.message),
+            }
+        }
+    }
+
+    fn source(&self, file: &Path) -> SourceFile {
+        return SourceFile {
+            path: file.to_path_buf(),
+            docs: self.docs.clone(),
+            skipped: self.skipped,
+        };
+    }
+}
+
+/// The 64-bit FNV-1a hash of `content`; unlike `DefaultHasher`, it is the same for every
+/// build, so that it can be stored in the cache
+fn content_hash(content: &[u8]) -> u64 {
+    let mut hash: u64 = 0xcbf29ce484222325;
+    for byte in content {
+        hash ^= u64::from(*byte);
+        hash = hash.wrapping_mul(0x100000001b3);
+    }
+    return hash;
+}
+
+/// Hashes the parts of the configuration that determine how source files are parsed; a
+/// cache written with different settings is discarded
+fn parse_fingerprint(config: &SrcDocConfig) -> u64 {
+    let mut comments = toml::to_string(&CommentConfigList {
+        comment: config.comment.as_ref().unwrap_or(&DEFAULT_COMMENT_MAP),
+    })
+    .unwrap_or_default();
+    comments.push_str(&format!("{:?} {:?}", config.shebang, config.tag_pattern));
+    comments.push_str(&toml::to_string(&config.tags).unwrap_or_default());
+    comments.push_str(&format!("{}", config.newline.normalize));
+    comments.push_str(&format!("{}", config.markdown.passthrough));
+    comments.push_str(&format!("{}", config.unicode.nfc));
+    comments.push_str(&format!("{:?}", config.define));
+    for source in &config.source {
+        if let Some(comment) = &source.comment {
+            comments.push_str(&source.path.display().to_string());
+            comments.push_str(&toml::to_string(&CommentConfigList { comment }).unwrap_or_default());
+        }
+    }
+    return content_hash(comments.as_bytes());
+}
+
+impl DocCache {
+    fn empty(fingerprint: u64) -> DocCache {
+        return DocCache {
+            version: String::from(env!("CARGO_PKG_VERSION")),
+            fingerprint,
+            files: HashMap::new(),
+        };
+    }
+
+    /// Loads the cache at `path`, falling back to an empty cache if it is missing,
+    /// unreadable or was written by a different version or configuration
+    fn load(path: &Path, fingerprint: u64) -> DocCache {
+        let cache = fs::read(path)
+            .ok()
+            .and_then(|x| serde_json::from_slice::<DocCache>(&x).ok());
+        return match cache {
+            Some(c) if c.version == env!("CARGO_PKG_VERSION") && c.fingerprint == fingerprint => c,
+            _ => DocCache::empty(fingerprint),
+        };
+    }
+
+    fn save(&self, path: &Path) -> Result<(), SrcDocError> {
+        let content = serde_json::to_vec(self).map_err(|e| {
+            return SrcDocError::new(format!("Error writing cache: {}", e))
+                .with_kind(ErrorKind::Io);
+        })?;
+        fs::write(path, content)?;
+        return Ok(());
+    }
+}
+
+/// The log targets of this program: detailed messages are grouped by the files read, the
+/// templates applied and the output written
+const LOG_TARGETS: [&str; 4] = ["simple_src_docs", "files", "templates", "output"];
+
+fn init_logger(args: &Args) {
+    let level = match (args.quiet, args.verbose) {
+        (true, _) => log::LevelFilter::Error,
+        (false, 0) => log::LevelFilter::Info,
+        (false, 1) => log::LevelFilter::Debug,
+        (false, _) => log::LevelFilter::Trace,
+    };
+    let mut builder = env_logger::Builder::new();
+    // messages from dependencies are only of interest when something goes wrong
+    builder.filter_level(level.min(log::LevelFilter::Warn));
+    for target in LOG_TARGETS {
+        builder.filter(Some(target), level);
+    }
+    let format = args.message_format;
+    builder
+        .parse_env("SIMPLE_SRC_DOCS_LOG")
+        .format(move |buf, record| {
+            let mut location = MessageLocation::default();
+            let _ = record.key_values().visit(&mut location);
+            if format == MessageFormat::Json {
+                let severity = match record.level() {
+                    log::Level::Warn => String::from("warning"),
+                    level => level.as_str().to_lowercase(),
+                };
+                let message = serde_json::json!({
+                    "severity": severity,
+                    "message": record.args().to_string(),
+                    "file": location.file,
+                    "line": location.line,
+                });
+                return writeln!(buf, "{}", message);
+            }
+            let prefix = match (&location.file, location.line) {
+                (Some(file), Some(line)) => format!("{}:{}: ", file, line),
+                (Some(file), None) => format!("{}: ", file),
+                _ => String::new(),
+            };
+            return match record.level() {
+                log::Level::Warn => writeln!(buf, "Warning: {}{}", prefix, record.args()),
+                _ => writeln!(buf, "{}{}", prefix, record.args()),
+            };
+        });
+    // route messages through the progress bars so that they don't garble each other
+    let logger = CountingLogger {
+        inner: builder.build(),
+    };
+    // warnings are always passed on to the logger, so that they are counted
+    let max_level = logger.inner.filter().max(log::LevelFilter::Warn);
+    let _ = indicatif_log_bridge::LogWrapper::new(PROGRESS.clone(), logger).try_init();
+    log::set_max_level(max_level);
+}
+
+/// The number of warnings reported so far, used by `--strict`
+static WARNINGS: AtomicUsize = AtomicUsize::new(0);
+
+thread_local! {
+    /// the warnings reported on this thread while `capture_warnings` runs
+    static CAPTURED: RefCell<Option<Vec<CachedWarning>>> = const { RefCell::new(None) };
+}
+
+/// Runs `f`, returning the warnings it reports (which are still logged) along with its
+/// result, so that they can be cached
+fn capture_warnings<R>(f: impl FnOnce() -> R) -> (R, Vec<CachedWarning>) {
+    CAPTURED.set(Some(Vec::new()));
+    let result = f();
+    return (result, CAPTURED.take().unwrap_or_default());
+}
+
+struct CountingLogger {
+    inner: env_logger::Logger,
+}
+
+impl log::Log for CountingLogger {
+    fn enabled(&self, metadata: &log::Metadata) -> bool {
+        return metadata.level() <= log::Level::Warn || self.inner.enabled(metadata);
+    }
+
+    fn log(&self, record: &log::Record) {
+        if record.level() == log::Level::Warn && LOG_TARGETS.contains(&record.target()) {
+            WARNINGS.fetch_add(1, atomic::Ordering::Relaxed);
+            CAPTURED.with_borrow_mut(|captured| {
+                if let Some(warnings) = captured {
+                    let mut location = MessageLocation::default();
+                    let _ = record.key_values().visit(&mut location);
+                    warnings.push(CachedWarning {
+                        target: String::from(record.target()),
+                        message: record.args().to_string(),
+                        file: location.file,
+                        line: location.line,
+                    });
+                }
+            });
+        }
+        self.inner.log(record);
+    }
+
+    fn flush(&self) {
+        self.inner.flush();
+    }
+}
+
+lazy_static! {
+    static ref PROGRESS: MultiProgress = MultiProgress::new();
+}
+
+/// A progress bar over `len` items, shown only when standard error is a terminal and no
+/// detailed or machine-readable messages are requested
+fn progress_bar(args: &Args, len: usize, msg: &'static str) -> ProgressBar {
+    let show = !args.quiet
+        && args.verbose == 0
+        && args.message_format == MessageFormat::Human
+        && io::stderr().is_terminal();
+    if !show {
+        return ProgressBar::hidden();
+    }
+    let style = ProgressStyle::with_template("{msg:>8} [{bar:40}] {pos}/{len} files")
+        .unwrap()
+        .progress_chars("=> ");
+    let bar = ProgressBar::new(len as u64)
+        .with_style(style)
+        .with_message(msg);
+    return PROGRESS.add(bar);
+}
+
+/// The `file` and `line` a log message refers to
+#[derive(Default)]
+struct MessageLocation {
+    file: Option<String>,
+    line: Option<u64>,
+}
+
+impl<'kvs> log::kv::VisitSource<'kvs> for MessageLocation {
+    fn visit_pair(
+        &mut self,
+        key: log::kv::Key<'kvs>,
+        value: log::kv::Value<'kvs>,
+    ) -> Result<(), log::kv::Error> {
+        match key.as_str() {
+            "file" => self.file = Some(value.to_string()),
+            "line" => self.line = value.to_u64(),
+            _ => (),
+        }
+        return Ok(());
+    }
+}
+
+fn run(args: &Args) -> Result<(), SrcDocError> {
+    if let Some(Command::Completions { shell }) = &args.command {
+        return print_completions(*shell);
+    }
+    if args.generate_man {
+        return print_man_page();
+    }
+    let destination = &args.dest;
+    if !destination.exists() {
+        return Err(SrcDocError::new(format!(
+            "The destination path `{}` does not exist.",
+            destination.display()
+        ))
+        .with_kind(ErrorKind::Io));
+    }
+
+    if args.print_config {
+        return print_config(args);
+    }
+
+    match &args.command {
+        Some(Command::Init { force }) => return init_config(args, *force),
+        Some(Command::Validate) => return validate_config(args),
+        Some(Command::MigrateConfig { print }) => return migrate_config(args, *print),
+        Some(Command::Generate(generate)) => return generate_docs(args, generate),
+        Some(Command::Check { source }) => {
+            let (outputs, _) = generate_outputs(args, source)?;
+            return check_outputs(&outputs);
+        }
+        Some(Command::Watch { clean, source }) => return watch(args, source, *clean),
+        Some(Command::Tags { source }) => return list_tags(args, source),
+        Some(Command::Stats { source }) => return print_stats(args, source),
+        Some(Command::Clean { source }) => {
+            let (outputs, roots) = generate_outputs(args, source)?;
+            return clean_outputs(args, &outputs, &roots);
+        }
+        Some(Command::Completions { .. }) => return Ok(()),
+        None => return generate_docs(args, &args.generate),
+    }
+}
+
+fn generate_docs(args: &Args, generate: &GenerateArgs) -> Result<(), SrcDocError> {
+    let destination = &args.dest;
+    let (outputs, roots) = generate_outputs(args, &generate.source)?;
+
+    if let Some(file) = &generate.stdout {
+        let target = destination.join(file);
+        // files routed by a `dest` entry are given by their path
+        return match outputs
+            .iter()
+            .find(|(path, _)| *path == target || path == file)
+        {
+            Some((_, content)) => {
+                print!("{}", content);
+                Ok(())
+            }
+            None => Err(SrcDocError::new(format!(
+                "No documentation is generated for the output file `{}`.",
+                file.display()
+            ))),
+        };
+    }
+
+    if generate.dry_run {
+        println!("Dry run: no files were written. Planned writes:");
+        for (path, content) in &outputs {
+            let action = if path.exists() { "overwrite" } else { "create" };
+            println!(
+                " - {} `{}` ({} bytes)",
+                action,
+                path.display(),
+                content.len()
+            );
+        }
+        if generate.clean {
+            for path in stale_outputs(destination, &roots, &outputs) {
+                println!(" - delete `{}`", path.display());
+            }
+        }
+        return Ok(());
+    }
+
+    if generate.check {
+        return check_outputs(&outputs);
+    }
+
+    if generate.diff {
+        for (path, content) in &outputs {
+            let (existing, old_name) = match fs::read_to_string(path) {
+                Ok(existing) => (existing, path.display().to_string()),
+                Err(_) => (String::new(), String::from("/dev/null")),
+            };
+            if existing == *content {
+                continue;
+            }
+            let new_name = path.display().to_string();
+            let diff = TextDiff::from_lines(&existing, content);
+            print!("{}", diff.unified_diff().header(&old_name, &new_name));
+        }
+        return Ok(());
+    }
+
+    return write_docs(args, &outputs, &roots, generate.clean);
+}
+
+/// Writes the rendered `outputs` to their files and updates the manifest, deleting stale
+/// files (within `roots`) if `clean` is set
+fn write_docs(
+    args: &Args,
+    outputs: &[(PathBuf, String)],
+    roots: &[PathBuf],
+    clean: bool,
+) -> Result<(), SrcDocError> {
+    let destination = &args.dest;
+    debug!(target: "output", "Writing doc files:");
+    let progress = progress_bar(args, outputs.len(), "Writing");
+    for (path, content) in outputs {
+        debug!(target: "output", " - {}", path.display());
+        fs::create_dir_all(path.parent().unwrap())?;
+        let mut io = File::create(path)?;
+        write!(io, "{}", content)?;
+        record(|summary| summary.outputs_written += 1);
+        progress.inc(1);
+    }
+    progress.finish_and_clear();
+    let stale = stale_outputs(destination, roots, outputs);
+    let mut generated: Vec<&PathBuf> = outputs.iter().map(|(path, _)| path).collect();
+    if clean {
+        remove_outputs(args, &stale)?;
+    } else {
+        // keep track of stale files so that a later `clean` can still find them
+        generated.extend(&stale);
+    }
+    write_manifest(destination, &generated)?;
+    info!("Successfully generated documentation.");
+    return Ok(());
+}
+
+/// Generates the documentation again each time one of the `sources` or the config file
+/// changes; changes to the files written by the last run (the generated files, the manifest
+/// and the cache) are ignored
+fn watch(args: &Args, sources: &[PathBuf], clean: bool) -> Result<(), SrcDocError> {
+    if sources.iter().any(|s| s.as_os_str() == "-") {
+        return Err(SrcDocError::new(String::from(
+            "Standard input (`-`) can't be watched for changes.",
+        ))
+        .with_kind(ErrorKind::Usage));
+    }
+    let watch_error = |e: notify::Error| {
+        return SrcDocError::new(format!("Error watching files: {}", e)).with_kind(ErrorKind::Io);
+    };
+    let (sender, events) = mpsc::channel();
+    let mut watcher = notify::recommended_watcher(sender).map_err(watch_error)?;
+    let mut watched_sources = Vec::new();
+    for source in sources {
+        // watch absolute paths, so that event paths can be compared against `<DEST>`
+        let source = fs::canonicalize(source).map_err(|e| {
+            return SrcDocError::from(e).with_file(source);
+        })?;
+        watcher
+            .watch(&source, RecursiveMode::Recursive)
+            .map_err(watch_error)?;
+        watched_sources.push(source);
+    }
+    let config = find_config(args, sources).unwrap_or_else(|| default_config_path(&args.dest));
+    let config = fs::canonicalize(&config)
+        .or_else(|_| std::path::absolute(&config))
+        .unwrap_or(config);
+    let destination = fs::canonicalize(&args.dest)?;
+    // the directories of the config files; editors often save a file by replacing it, which
+    // only a watch of its directory keeps track of
+    let mut config_dirs: Vec<PathBuf> = Vec::new();
+    // the files written (or deleted) by the last run, which may lie outside of `<DEST>`
+    // (see `dest`)
+    let mut written: Vec<PathBuf> = Vec::new();
+    let bookkeeping = [manifest_path(&destination), cache_path(&destination)];
+
+    loop {
+        WARNINGS.store(0, atomic::Ordering::Relaxed);
+        // the files of the last manifest are the ones a run with `--clean` deletes
+        let previous = read_manifest(&destination);
+        let result = generate_outputs(args, sources).and_then(|(o, roots)| {
+            write_docs(args, &o, &roots, clean)?;
+            return Ok(o);
+        });
+        match result {
+            Ok(outputs) => {
+                written = outputs
+                    .iter()
+                    .filter_map(|(path, _)| fs::canonicalize(path).ok())
+                    .chain(previous)
+                    .collect();
+            }
+            Err(e) => report_error(&e),
+        }
+        // the included files can change along with the config file
+        let config_files = config_files(&config);
+        for dir in config_files.iter().filter_map(|f| f.parent()) {
+            if !config_dirs.iter().any(|d| d == dir) && dir.is_dir() {
+                watcher
+                    .watch(dir, RecursiveMode::NonRecursive)
+                    .map_err(watch_error)?;
+                config_dirs.push(dir.to_path_buf());
+            }
+        }
+        let is_relevant = |p: &PathBuf| {
+            return config_files.contains(p)
+                || (watched_sources.iter().any(|s| p.starts_with(s))
+                    && !bookkeeping.contains(p)
+                    // the written files, or the directories created to hold them
+                    && !written.iter().any(|w| w.starts_with(p)));
+        };
+        info!("Watching for changes...");
+        loop {
+            match events.recv() {
+                Ok(Ok(event)) if event.kind.is_access() => (),
+                Ok(Ok(event)) => {
+                    if let Some(p) = event.paths.iter().find(|p| config_files.contains(p)) {
+                        info!("The config file `{}` changed, reloading it.", p.display());
+                    }
+                    if event.paths.iter().any(is_relevant) {
+                        break;
+                    }
+                }
+                Ok(Err(e)) => warn!("Error watching files: {}", e),
+                Err(_) => {
+                    let msg = String::from("Stopped watching files.");
+                    return Err(SrcDocError::new(msg).with_kind(ErrorKind::Io));
+                }
+            }
+        }
+        // wait for a burst of changes (e.g. an editor saving several files) to settle
+        while events.recv_timeout(Duration::from_millis(100)).is_ok() {}
+        debug!(target: "files", "Change detected, generating documentation again");
+    }
+}
+
+/// Compares the rendered `outputs` against the files in `<DEST>`, failing if any differ
+fn check_outputs(outputs: &[(PathBuf, String)]) -> Result<(), SrcDocError> {
+    let mut stale = Vec::new();
+    for (path, content) in outputs {
+        debug!(target: "output", "Checking {}", path.display());
+        match fs::read_to_string(path) {
+            Ok(existing) if existing == *content => (),
+            _ => stale.push(path),
+        }
+    }
+    if stale.is_empty() {
+        println!("Documentation is up to date.");
+        return Ok(());
+    }
+    let mut msg = String::from("The following documentation files are out of date:");
+    for path in stale {
+        msg.push_str(&format!("\n - {}", path.display()));
+    }
+    return Err(SrcDocError::new(msg));
+}
+
+/// The path of each output file, with its rendered content
+type Outputs = Vec<(PathBuf, String)>;
+
+/// Extracts the docs from `sources` and renders every output file in memory; also returns
+/// the directories that output files are written to: `<DEST>` and the `dest` paths
+fn generate_outputs(
+    args: &Args,
+    sources: &[PathBuf],
+) -> Result<(Outputs, Vec<PathBuf>), SrcDocError> {
+    let config = load_config(args, sources)?;
+    let mut all_docs: Vec<DocData> = read_sources(args, &config, sources)?
+        .into_iter()
+        .flat_map(|file| file.docs)
+        .collect();
+    for doc in &mut all_docs {
+        config.tags.apply_defaults(doc);
+        doc.render_tags(&config.variables, config.tags.order)?;
+        for rule in &config.rules {
+            rule.check(doc)?;
+        }
+    }
+    all_docs.sort_by(|a, b| a.order.cmp(&b.order));
+    let mut docmap = config.apply(&all_docs.iter().collect())?;
+    let outputs = render_outputs(&args.dest, &mut docmap, &config)?;
+    let warnings = WARNINGS.load(atomic::Ordering::Relaxed);
+    if (args.strict || config.strict) && warnings > 0 {
+        return Err(SrcDocError::new(format!(
+            "Stopping because {} warning(s) were reported in strict mode.",
+            warnings
+        )));
+    }
+    let mut roots = vec![args.dest.clone()];
+    roots.extend(config.dest.iter().map(|d| d.path.clone()));
+    return Ok((outputs, roots));
+}
+
+/// Resolve each output file against `destination` (or the `dest` entry of `config` routing
+/// it elsewhere), concatenate its items by `order`, wrap
+/// them and convert the result to the file's format, as set by the `output` of `config`
+fn render_outputs(
+    destination: &Path,
+    docmap: &mut HashMap<String, Vec<(OrderKey, String)>>,
+    config: &SrcDocConfig,
+) -> Result<Vec<(PathBuf, String)>, SrcDocError> {
+    let mut outputs = Vec::new();
+    for (file, items) in docmap.iter_mut() {
+        let destination = config.dest_for(Path::new(file)).unwrap_or(destination);
+        let path = destination.join(file);
+        if !path.starts_with(destination) {
+            return Err(SrcDocError::new(format!(
+                "File path `{}` is not a child of the destination path `{}`.",
+                path.display(),
+                destination.display()
+            ))
+            .with_kind(ErrorKind::Template));
+        }
+
+        items.sort_by(|a, b| a.0.cmp(&b.0));
+        let mut content = String::new();
+        for (_, body) in items.iter() {
+            content.push_str(body);
+        }
+        let content = config.output.wrap(file, content, &config.variables)?;
+        let format = output_format(&config.output.format, Path::new(file));
+        let content = config.newline.apply(format.emit(&content));
+        outputs.push((path, content));
+    }
+    outputs.sort_by(|a, b| a.0.cmp(&b.0));
+    return Ok(outputs);
+}
+
+fn default_config_path(destination: &Path) -> PathBuf {
+    return destination.join(".simple-src-docs.config.toml");
+}
+
+// Output Formats //////////////////////////////////////////////////////////////////////////
+
+/// The format an output file is written in; templates always render markdown, which is
+/// converted to the other formats before writing
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+#[serde(rename_all = "lowercase")]
+enum OutputFormat {
+    Markdown,
+    Html,
+    Asciidoc,
+}
+
+impl OutputFormat {
+    fn emit(self, markdown: &str) -> String {
+        return match self {
+            OutputFormat::Markdown => String::from(markdown),
+            OutputFormat::Html => {
+                let mut html = String::new();
+                pulldown_cmark::html::push_html(&mut html, pulldown_cmark::Parser::new(markdown));
+                html
+            }
+            OutputFormat::Asciidoc => markdown_to_asciidoc(markdown),
+        };
+    }
+}
+
+/// The `output` table: the format of the output files matching each glob, and the
+/// headers and footers placed around them
+#[derive(Serialize, Deserialize, Default)]
+struct OutputConfig {
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    wrap: Vec<OutputWrap>,
+    #[serde(flatten)]
+    format: BTreeMap<String, OutputFormat>,
+}
+
+/// Mustache templates rendered once per output file matching `file` and placed before and
+/// after its concatenated items
+#[derive(Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct OutputWrap {
+    #[serde(deserialize_with = "str_to_glob", serialize_with = "glob_to_str")]
+    file: Glob<'static>,
+    #[serde(default)]
+    header: String,
+    /// a file holding the `header` template, relative to the config file
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    header_file: Option<PathBuf>,
+    #[serde(default)]
+    footer: String,
+    /// a file holding the `footer` template, relative to the config file
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    footer_file: Option<PathBuf>,
+}
+
+impl OutputConfig {
+    fn is_empty(&self) -> bool {
+        return self.wrap.is_empty() && self.format.is_empty();
+    }
+
+    /// Places the headers and footers of the `wrap` entries matching `file` around `content`,
+    /// the first matching entry outermost
+    fn wrap(
+        &self,
+        file: &str,
+        content: String,
+        variables: &HashMap<String, String>,
+    ) -> Result<String, TemplateError> {
+        let path = Path::new(file);
+        let mut builder = MapBuilder::new();
+        for (key, val) in variables {
+            builder = builder.insert_str(key, val);
+        }
+        let data = builder.insert_str("file", file).build();
+
+        let mut result = content;
+        for w in self.wrap.iter().rev() {
+            let matches =
+                w.file.is_match(path) || path.file_name().is_some_and(|name| w.file.is_match(name));
+            if !matches {
+                continue;
+            }
+            let header = mustache::compile_str(&w.header)?.render_data_to_string(&data)?;
+            let footer = mustache::compile_str(&w.footer)?.render_data_to_string(&data)?;
+            result = format!("{}{}{}", header, result, footer);
+        }
+        return Ok(result);
+    }
+}
+
+/// The format of the output `file` (relative to `<DEST>`): the longest glob of `formats`
+/// matching its path or name, defaulting to markdown
+fn output_format(formats: &BTreeMap<String, OutputFormat>, file: &Path) -> OutputFormat {
+    let mut result = (0, OutputFormat::Markdown);
+    for (glob, format) in formats {
+        // the globs are checked by `output_globs` when the config is validated
+        let Ok(g) = Glob::new(glob) else {
+            continue;
+        };
+        let matches = g.is_match(file) || file.file_name().is_some_and(|name| g.is_match(name));
+        if matches && glob.len() > result.0 {
+            result = (glob.len(), *format);
+        }
+    }
+    return result.1;
+}
+
+fn markdown_to_asciidoc(markdown: &str) -> String {
+    use pulldown_cmark::{CodeBlockKind, Event, Tag, TagEnd};
+
+    let mut out = String::new();
+    // where the text of each enclosing link or image starts, with its target
+    let mut links: Vec<(usize, String, bool)> = Vec::new();
+    // the item marker of each enclosing list
+    let mut lists: Vec<char> = Vec::new();
+    for event in pulldown_cmark::Parser::new(markdown) {
+        match event {
+            Event::Start(Tag::Heading { level, .. }) => {
+                out.push_str(&"=".repeat(level as usize));
+                out.push(' ');
+            }
+            Event::End(TagEnd::Heading(_)) | Event::End(TagEnd::Paragraph) => {
+                out.push_str(if lists.is_empty() { "\n\n" } else { "\n" });
+            }
+            Event::Start(Tag::CodeBlock(kind)) => {
+                if let CodeBlockKind::Fenced(lang) = kind {
+                    if !lang.is_empty() {
+                        out.push_str(&format!("[source,{}]\n", lang));
+                    }
+                }
+                out.push_str("----\n");
+            }
+            Event::End(TagEnd::CodeBlock) => out.push_str("----\n\n"),
+            Event::Start(Tag::BlockQuote(_)) => out.push_str("____\n"),
+            Event::End(TagEnd::BlockQuote(_)) => out.push_str("____\n\n"),
+            Event::Start(Tag::List(first)) => {
+                if !lists.is_empty() && !out.ends_with('\n') {
+                    out.push('\n');
+                }
+                lists.push(if first.is_some() { '.' } else { '*' });
+            }
+            Event::End(TagEnd::List(_)) => {
+                lists.pop();
+                if lists.is_empty() {
+                    out.push('\n');
+                }
+            }
+            Event::Start(Tag::Item) => {
+                let marker = lists.last().copied().unwrap_or('*');
+                out.push_str(&marker.to_string().repeat(lists.len()));
+                out.push(' ');
+            }
+            Event::End(TagEnd::Item) if !out.ends_with('\n') => out.push('\n'),
+            Event::Start(Tag::Emphasis) | Event::End(TagEnd::Emphasis) => out.push('_'),
+            Event::Start(Tag::Strong) | Event::End(TagEnd::Strong) => out.push('*'),
+            Event::Start(Tag::Link { dest_url, .. }) => {
+                links.push((out.len(), dest_url.to_string(), false));
+            }
+            Event::Start(Tag::Image { dest_url, .. }) => {
+                links.push((out.len(), dest_url.to_string(), true));
+            }
+            Event::End(TagEnd::Link) | Event::End(TagEnd::Image) => {
+                if let Some((start, url, image)) = links.pop() {
+                    let text = out.split_off(start);
+                    let name = if image { "image" } else { "link" };
+                    out.push_str(&format!("{}:{}[{}]", name, url, text));
+                }
+            }
+            Event::Code(code) => out.push_str(&format!("`+{}+`", code)),
+            Event::Text(text) | Event::Html(text) | Event::InlineHtml(text) => {
+                out.push_str(&text);
+            }
+            Event::SoftBreak => out.push('\n'),
+            Event::HardBreak => out.push_str(" +\n"),
+            Event::Rule => out.push_str("'''\n\n"),
+            _ => {}
+        }
+    }
+    return out;
+}
+
+fn ignore_globs(ignore: &[String]) -> Result<(), ValidationError> {
+    return check_globs("ignore", ignore);
+}
+
+fn output_globs(output: &OutputConfig) -> Result<(), ValidationError> {
+    return check_globs("output", output.format.keys());
+}
+
+/// Fails on the first of the `globs` of the setting `name` that isn't a valid glob
+fn check_globs<'a>(
+    name: &'static str,
+    globs: impl IntoIterator<Item = &'a String>,
+) -> Result<(), ValidationError> {
+    for glob in globs {
+        if let Err(e) = Glob::new(glob) {
+            return Err(ValidationError::new(name)
+                .with_message(format!("invalid glob `{}`: {}", glob, e).into()));
+        }
+    }
+    return Ok(());
+}
+
+// Manifest ////////////////////////////////////////////////////////////////////////////////
+
+fn manifest_path(destination: &Path) -> PathBuf {
+    return destination.join(".simple-src-docs.manifest");
+}
+
+/// The generated files recorded by the last run, resolved against `destination`
+fn read_manifest(destination: &Path) -> Vec<PathBuf> {
+    return match fs::read_to_string(manifest_path(destination)) {
+        Ok(text) => text
+            .lines()
+            .filter(|line| !line.trim().is_empty())
+            .map(|line| destination.join(line))
+            .collect(),
+        Err(_) => Vec::new(),
+    };
+}
+
+fn write_manifest(destination: &Path, files: &[&PathBuf]) -> Result<(), SrcDocError> {
+    let mut text = String::new();
+    for path in files {
+        match path.strip_prefix(destination) {
+            Ok(relative) => {
+                let names: Vec<_> = relative.iter().map(|x| x.to_string_lossy()).collect();
+                text.push_str(&names.join("/"));
+            }
+            // files routed outside of `destination` are recorded by their absolute path
+            Err(_) => {
+                let absolute = std::path::absolute(path).unwrap_or(path.to_path_buf());
+                text.push_str(&absolute.to_string_lossy());
+            }
+        }
+        text.push('\n');
+    }
+    fs::write(manifest_path(destination), text)?;
+    return Ok(());
+}
+
+/// Previously generated files that still exist but are not part of `outputs`; files of the
+/// manifest outside of the output directories `roots` are left alone
+fn stale_outputs(
+    destination: &Path,
+    roots: &[PathBuf],
+    outputs: &[(PathBuf, String)],
+) -> Vec<PathBuf> {
+    let roots: Vec<PathBuf> = roots
+        .iter()
+        .filter_map(|r| fs::canonicalize(r).ok())
+        .collect();
+    return read_manifest(destination)
+        .into_iter()
+        .filter(|path| {
+            let path = std::path::absolute(path).unwrap_or(path.clone());
+            return path.is_file()
+                && !outputs
+                    .iter()
+                    .any(|(p, _)| std::path::absolute(p).is_ok_and(|p| p == path));
+        })
+        .filter(|path| {
+            let inside =
+                fs::canonicalize(path).is_ok_and(|p| roots.iter().any(|r| p.starts_with(r)));
+            if !inside {
+                warn!(
+                    file:% = manifest_path(destination).display();
+                    "Ignoring `{}`, which is outside of the output directories",
+                    path.display()
+                );
+            }
+            return inside;
+        })
+        .collect();
+}
+
+/// Deletes `files` along with any of their parent directories that are left empty
+fn remove_outputs(args: &Args, files: &[PathBuf]) -> Result<(), SrcDocError> {
+    for file in files {
+        debug!(target: "output", "Deleting stale file {}", file.display());
+        fs::remove_file(file)?;
+        record(|summary| summary.outputs_deleted += 1);
+        let mut dir = file.parent();
+        while let Some(d) = dir {
+            if !d.starts_with(&args.dest) || d == args.dest || fs::remove_dir(d).is_err() {
+                break;
+            }
+            dir = d.parent();
+        }
+    }
+    return Ok(());
+}
+
+fn clean_outputs(
+    args: &Args,
+    outputs: &[(PathBuf, String)],
+    roots: &[PathBuf],
+) -> Result<(), SrcDocError> {
+    let stale = stale_outputs(&args.dest, roots, outputs);
+    remove_outputs(args, &stale)?;
+    let generated: Vec<&PathBuf> = outputs.iter().map(|(path, _)| path).collect();
+    write_manifest(&args.dest, &generated)?;
+    println!("Deleted {} stale documentation file(s).", stale.len());
+    return Ok(());
+}
+
+// Run Report //////////////////////////////////////////////////////////////////////////////
+
+/// The summary of a run written by `--report`
+#[derive(Serialize, Default)]
+struct RunSummary {
+    success: bool,
+    error: Option<String>,
+    files_scanned: usize,
+    files_skipped: usize,
+    docs_extracted: usize,
+    outputs_written: usize,
+    outputs_deleted: usize,
+    warnings: usize,
+}
+
+lazy_static! {
+    static ref SUMMARY: Mutex<RunSummary> = Mutex::new(RunSummary::default());
+}
+
+fn record(update: impl FnOnce(&mut RunSummary)) {
+    update(&mut SUMMARY.lock().unwrap());
+}
+
+fn write_report(path: &Path, error: Option<&str>) -> Result<(), SrcDocError> {
+    let mut summary = SUMMARY.lock().unwrap();
+    summary.success = error.is_none();
+    summary.error = error.map(String::from);
+    summary.warnings = WARNINGS.load(atomic::Ordering::Relaxed);
+
+    let content = if path.extension().is_some_and(|ext| ext == "md") {
+        let mut md = String::from("# simple-src-docs report\n\n");
+        match &summary.error {
+            None => md.push_str("Status: success\n\n"),
+            Some(e) => md.push_str(&format!("Status: failure ({})\n\n", e)),
+        }
+        md.push_str("| | Count |\n|---|---|\n");
+        md.push_str(&format!("| Files scanned | {} |\n", summary.files_scanned));
+        md.push_str(&format!("| Files skipped | {} |\n", summary.files_skipped));
+        md.push_str(&format!(
+            "| Doc blocks extracted | {} |\n",
+            summary.docs_extracted
+        ));
+        md.push_str(&format!(
+            "| Outputs written | {} |\n",
+            summary.outputs_written
+        ));
+        md.push_str(&format!(
+            "| Outputs deleted | {} |\n",
+            summary.outputs_deleted
+        ));
+        md.push_str(&format!("| Warnings | {} |\n", summary.warnings));
+        md
+    } else {
+        let mut json = serde_json::to_string_pretty(&*summary).map_err(|e| {
+            return SrcDocError::new(format!("Error writing report: {}", e))
+                .with_kind(ErrorKind::Io);
+        })?;
+        json.push('\n');
+        json
+    };
+    fs::write(path, content).map_err(|e| {
+        return SrcDocError::new(format!("Error writing report: {}", e))
+            .with_file(path)
+            .with_kind(ErrorKind::Io);
+    })?;
+    return Ok(());
+}
+
+// Tag Listing /////////////////////////////////////////////////////////////////////////////
+
+fn list_tags(args: &Args, sources: &[PathBuf]) -> Result<(), SrcDocError> {
+    let config = load_config(args, sources)?;
+    let mut tags: HashMap<String, (usize, Vec<PathBuf>)> = HashMap::new();
+    for file in read_sources(args, &config, sources)? {
+        for doc in &file.docs {
+            for tag in doc.tags.keys() {
+                let (count, files) = tags.entry(tag.clone()).or_default();
+                *count += 1;
+                if !files.contains(&file.path) {
+                    files.push(file.path.clone());
+                }
+            }
+        }
+    }
+
+    let mut names: Vec<&String> = tags.keys().collect();
+    names.sort();
+    for name in names {
+        let (count, files) = &tags[name];
+        let blocks = if *count == 1 { "block" } else { "blocks" };
+        println!("@{} ({} {})", name, count, blocks);
+        for file in files {
+            println!("    {}", file.display());
+        }
+    }
+    return Ok(());
+}
+
+// Statistics //////////////////////////////////////////////////////////////////////////////
+
+fn print_stats(args: &Args, sources: &[PathBuf]) -> Result<(), SrcDocError> {
+    let config = load_config(args, sources)?;
+    let files = read_sources(args, &config, sources)?;
+    let scanned = files.len();
+    let skipped = files.iter().filter(|f| f.skipped).count();
+    let docs: Vec<&DocData> = files.iter().flat_map(|f| &f.docs).collect();
+    let without_file = docs.iter().filter(|d| !d.tags.contains_key("file")).count();
+    let outputs = config.apply(&docs)?.len();
+
+    println!("Files scanned:         {}", scanned);
+    println!("Files skipped:         {}", skipped);
+    println!("Doc blocks extracted:  {}", docs.len());
+    println!("Blocks without @file:  {}", without_file);
+    println!("Output files produced: {}", outputs);
+    return Ok(());
+}
+
+// Shell Completions ///////////////////////////////////////////////////////////////////////
+
+fn print_completions(shell: clap_complete::Shell) -> Result<(), SrcDocError> {
+    let mut command = Args::command();
+    let name = command.get_name().to_string();
+    let mut script = Vec::new();
+    clap_complete::generate(shell, &mut command, name, &mut script);
+    io::stdout().write_all(&script)?;
+    return Ok(());
+}
+
+// Man Page ////////////////////////////////////////////////////////////////////////////////
+
+/// The README, whose documentation of the config file is the man page's CONFIGURATION section
+const README: &str = include_str!("../README.md");
+
+/// The config file section of the README (its paragraphs up to the example config file), as
+/// roff: each `` `key`: `` list item is a tagged paragraph, nested lists are indented and
+/// `code` is set in bold
+fn man_configuration() -> String {
+    lazy_static! {
+        static ref ITEM: Regex = Regex::new(r"^\s*- (?:((?:`[^`]+`(?:, )?)+): )?(.*)$").unwrap();
+        static ref LINK: Regex = Regex::new(r"\[([^\]]*)\]\([^)]*\)").unwrap();
+    }
+    let start = README
+        .find("The config file is a TOML file")
+        .unwrap_or(README.len());
+    let end = (README[start..].find("\nExample config file")).map_or(README.len(), |e| start + e);
+
+    // the indent, list item key (if an item) and joined lines of each paragraph
+    let mut paragraphs: Vec<(usize, Option<Option<&str>>, String)> = Vec::new();
+    let mut open = false;
+    for line in README[start..end].lines() {
+        let indent = line.len() - line.trim_start().len();
+        if line.trim().is_empty() {
+            open = false;
+        } else if let Some(m) = ITEM.captures(line) {
+            let key = m.get(1).map(|k| k.as_str());
+            paragraphs.push((indent, Some(key), m[2].to_string()));
+            open = true;
+        } else if let (true, Some((_, _, text))) = (open, paragraphs.last_mut()) {
+            text.push(' ');
+            text.push_str(line.trim());
+        } else {
+            paragraphs.push((indent, None, line.trim().to_string()));
+            open = true;
+        }
+    }
+
+    let mut page = String::from(".SH CONFIGURATION\n");
+    // the indents of the list items that the current paragraph is within
+    let mut items: Vec<usize> = Vec::new();
+    for (indent, item, text) in paragraphs {
+        while items
+            .last()
+            .is_some_and(|i| *i > indent || (item.is_none() && *i == indent))
+        {
+            items.pop();
+            if !items.is_empty() {
+                page.push_str(".RE\n");
+            }
+        }
+        match item {
+            Some(key) => {
+                if items.last().is_none_or(|i| *i < indent) {
+                    if !items.is_empty() {
+                        page.push_str(".RS\n");
+                    }
+                    items.push(indent);
+                }
+                match key {
+                    Some(key) => page.push_str(&format!(".TP\n{}\n", roff_text(key))),
+                    None => page.push_str(".IP \\(bu 2\n"),
+                }
+            }
+            None if items.is_empty() => page.push_str(".PP\n"),
+            None => page.push_str(".IP\n"),
+        }
+        let text = roff_text(&LINK.replace_all(&text, "$1"));
+        if text.starts_with(['.', '\'']) {
+            page.push_str("\\&");
+        }
+        page.push_str(&text);
+        page.push('\n');
+    }
+    for _ in 1..items.len() {
+        page.push_str(".RE\n");
+    }
+    return page;
+}
+
+/// Escapes `text` for roff, setting its markdown `code` spans in bold
+fn roff_text(text: &str) -> String {
+    let mut roff = String::new();
+    for (i, span) in text.split('`').enumerate() {
+        let span = span.replace('\\', "\\e");
+        match i % 2 {
+            0 => roff.push_str(&span),
+            _ => roff.push_str(&format!("\\fB{}\\fR", span.replace('-', "\\-"))),
+        }
+    }
+    return roff;
+}
+
+fn print_man_page() -> Result<(), SrcDocError> {
+    let man = clap_mangen::Man::new(Args::command());
+    let mut page = Vec::new();
+    man.render_title(&mut page)?;
+    man.render_name_section(&mut page)?;
+    man.render_synopsis_section(&mut page)?;
+    man.render_description_section(&mut page)?;
+    man.render_options_section(&mut page)?;
+    man.render_subcommands_section(&mut page)?;
+    page.extend_from_slice(man_configuration().as_bytes());
+    man.render_version_section(&mut page)?;
+    io::stdout().write_all(&page)?;
+    return Ok(());
+}
+
+// Config Scaffolding //////////////////////////////////////////////////////////////////////
+
+const INIT_CONFIG_HEADER: &str = r#"# Configuration for simple-src-docs (see README.md for the full specification)
+
+# Fail, instead of warning, about problems in doc blocks (same as `--strict`)
+# strict = true
+
+[header]
+version = "0.2.1"
+# Set to false to only use the `[[comment]]` entries defined in this file
+# use_defaults = false
+
+# Comment syntax
+#
+# Each `[[comment]]` entry describes how to find comments in files matching `extension`.
+# Entries defined here are tried before the built-in entries, which are available unless
+# `header.use_defaults` is false and are listed below for reference.
+
+"#;
+
+const INIT_CONFIG_TEMPLATES: &str = r#"
+# Templates
+#
+# `foreach` templates are rendered once for every doc block that has all of the listed
+# `tags`; `all` templates are rendered once with every such block in the list `items`. All
+# fields are mustache templates and `{{{__body__}}}` is the untagged text of a block.
+
+# [[template.foreach]]
+# tags = ["command"]
+# file = "commands/{{command}}.md"
+# output = """
+# # `{{command}}`
+#
+# {{{__body__}}}
+# """
+
+# [[template.all]]
+# tags = ["command"]
+# file = "commands/index.md"
+# order = 0
+# output = """
+# # Commands
+#
+# {{#items}}
+# - [`{{command}}`](./{{command}}.md)
+# {{/items}}
+# """
+"#;
+
+#[derive(Serialize)]
+struct CommentConfigList<'a> {
+    comment: &'a Vec<CommentConfig>,
+}
+
+fn init_config(args: &Args, force: bool) -> Result<(), SrcDocError> {
+    let path = match &args.config {
+        Some(x) => x.clone(),
+        None => default_config_path(&args.dest),
+    };
+    if path.exists() && !force {
+        return Err(SrcDocError::new(format!(
+            "The config file `{}` already exists, use `--force` to overwrite it.",
+            path.display()
+        )));
+    }
+
+    let comments = toml::to_string(&CommentConfigList {
+        comment: &DEFAULT_COMMENT_MAP,
+    })
+    .map_err(|e| SrcDocError::new(format!("Error serializing comment map: {}", e)))?;
+    let mut content = String::from(INIT_CONFIG_HEADER);
+    for line in comments.lines() {
+        if line.is_empty() {
+            content.push('\n');
+        } else {
+            content.push_str(&format!("# {}\n", line));
+        }
+    }
+    content.push_str(INIT_CONFIG_TEMPLATES);
+
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    fs::write(&path, content)?;
+    println!("Wrote config file `{}`.", path.display());
+    return Ok(());
+}
+
+// Config Printing /////////////////////////////////////////////////////////////////////////
+
+fn print_config(args: &Args) -> Result<(), SrcDocError> {
+    let mut config = load_config(args, args.sources())?;
+    let error = |e: toml::ser::Error| SrcDocError::new(format!("Error serializing config: {}", e));
+    // the built-in entries are commented out, since a config file reading them back would
+    // have them as its own entries, which take precedence over the built-in ones
+    let (builtin, comment): (Vec<_>, Vec<_>) = config
+        .comment
+        .take()
+        .into_iter()
+        .flatten()
+        .partition(|c| c.builtin);
+    config.comment = Some(comment).filter(|c| !c.is_empty());
+    let mut content = toml::to_string(&config).map_err(error)?;
+    if !builtin.is_empty() {
+        #[derive(Serialize)]
+        struct Builtin {
+            comment: Vec<CommentConfig>,
+        }
+        let builtin = toml::to_string(&Builtin { comment: builtin }).map_err(error)?;
+        content.push_str("\n# The built-in comment syntaxes (see `header.use_defaults`):\n");
+        for line in builtin.lines() {
+            content.push_str(format!("# {}", line).trim_end());
+            content.push('\n');
+        }
+    }
+    print!("{}", content);
+    return Ok(());
+}
+
+// Config Validation ///////////////////////////////////////////////////////////////////////
+
+/// Source locations of the template fields, used to give context for template errors
+#[derive(Deserialize)]
+struct ConfigSpans {
+    #[serde(default)]
+    template: Option<TemplateSpans>,
+    #[serde(default)]
+    source: Vec<SourceSpans>,
+}
+
+#[derive(Deserialize)]
+struct SourceSpans {
+    #[serde(default)]
+    template: Option<TemplateSpans>,
+}
+
+#[derive(Deserialize)]
+struct TemplateSpans {
+    #[serde(default)]
+    foreach: Vec<TemplateFieldSpans>,
+    #[serde(default)]
+    all: Vec<TemplateFieldSpans>,
+}
+
+#[derive(Deserialize)]
+struct TemplateFieldSpans {
+    file: Option<toml::Spanned<toml::Value>>,
+    order: Option<toml::Spanned<toml::Value>>,
+    output: Option<toml::Spanned<toml::Value>>,
+}
+
+fn validation_problems(prefix: &str, errors: &ValidationErrors, problems: &mut Vec<String>) {
+    let mut fields: Vec<_> = errors.errors().iter().collect();
+    fields.sort_by(|a, b| a.0.cmp(b.0));
+    for (field, kind) in fields {
+        let name = format!("{}{}", prefix, field);
+        match kind {
+            ValidationErrorsKind::Field(errs) => {
+                for e in errs {
+                    let msg = e.message.as_ref().unwrap_or(&e.code);
+                    if *field == "__all__" {
+                        // struct-level checks name the offending field in their message
+                        problems.push(format!("{}{}", prefix, msg));
+                    } else {
+                        problems.push(format!("`{}`: {}", name, msg));
+                    }
+                }
+            }
+            ValidationErrorsKind::Struct(errs) => {
+                validation_problems(&format!("{}.", name), errs, problems);
+            }
+            ValidationErrorsKind::List(items) => {
+                for (i, errs) in items {
+                    validation_problems(&format!("{}[{}].", name, i), errs, problems);
+                }
+            }
+        }
+    }
+}
+
+fn line_context(text: &str, span: &Option<toml::Spanned<toml::Value>>) -> String {
+    return match span {
+        Some(x) => {
+            let start = x.span().start;
+            let line = text[..start].matches('\n').count() + 1;
+            let line_text = text.lines().nth(line - 1).unwrap_or_default();
+            format!("\n     |\n{:>4} | {}\n     |", line, line_text)
+        }
+        None => String::new(),
+    };
+}
+
+fn check_template(
+    name: String,
+    renderer: &TemplateRenderer,
+    engine: Option<TemplateEngine>,
+    template: &str,
+    text: &str,
+    span: &Option<toml::Spanned<toml::Value>>,
+    problems: &mut Vec<String>,
+) {
+    if let Some(e) = renderer.problem(engine, template) {
+        problems.push(format!("`{}`: {}{}", name, e, line_context(text, span)));
+    }
+}
+
+/// Compiles each of `templates`, reporting problems under the config path `prefix`
+fn check_templates(
+    prefix: &str,
+    templates: &ConfigTemplates,
+    spans: Option<TemplateSpans>,
+    text: &str,
+    problems: &mut Vec<String>,
+) {
+    let template_spans = spans.unwrap_or(TemplateSpans {
+        foreach: Vec::new(),
+        all: Vec::new(),
+    });
+    let no_spans = TemplateFieldSpans {
+        file: None,
+        order: None,
+        output: None,
+    };
+    let renderer = TemplateRenderer::new(templates);
+    for (i, t) in templates.foreach.iter().flatten().enumerate() {
+        let span = template_spans.foreach.get(i).unwrap_or(&no_spans);
+        let name = format!("{}template.foreach[{}]", prefix, i);
+        check_template(
+            format!("{}.file", name),
+            &renderer,
+            t.engine,
+            &t.file,
+            text,
+            &span.file,
+            problems,
+        );
+        if let Right(order) = &t.order {
+            let field = format!("{}.order", name);
+            check_template(
+                field,
+                &renderer,
+                t.engine,
+                order,
+                text,
+                &span.order,
+                problems,
+            );
+        }
+        let field = format!("{}.output", name);
+        check_template(
+            field,
+            &renderer,
+            t.engine,
+            &t.output,
+            text,
+            &span.output,
+            problems,
+        );
+    }
+    for (i, t) in templates.all.iter().flatten().enumerate() {
+        let span = template_spans.all.get(i).unwrap_or(&no_spans);
+        let name = format!("{}template.all[{}]", prefix, i);
+        check_template(
+            format!("{}.file", name),
+            &renderer,
+            t.engine,
+            &t.file,
+            text,
+            &span.file,
+            problems,
+        );
+        let field = format!("{}.output", name);
+        check_template(
+            field,
+            &renderer,
+            t.engine,
+            &t.output,
+            text,
+            &span.output,
+            problems,
+        );
+    }
+}
+
+fn validate_config(args: &Args) -> Result<(), SrcDocError> {
+    let path = match &args.config {
+        Some(x) => x.clone(),
+        None => default_config_path(&args.dest),
+    };
+    if !path.is_file() {
+        return Err(SrcDocError::new(format!(
+            "The config file `{}` does not exist.",
+            path.display()
+        ))
+        .with_kind(ErrorKind::Config));
+    }
+    let text = fs::read_to_string(&path)?;
+    let mut config = SrcDocConfig::parse(&text, Some(&path), &[])?;
+    config.load_template_files(Some(&path))?;
+    // template locations are only reported for TOML files
+    let spans = match ConfigFormat::of(&path) {
+        ConfigFormat::Toml => toml::from_str::<ConfigSpans>(&text)?,
+        _ => ConfigSpans {
+            template: None,
+            source: Vec::new(),
+        },
+    };
+
+    let mut problems = Vec::new();
+    if let Err(e) = config.validate() {
+        validation_problems("", &e, &mut problems);
+    }
+
+    if let Some(templates) = &config.template {
+        check_templates("", templates, spans.template, &text, &mut problems);
+    }
+    for (i, w) in config.output.wrap.iter().enumerate() {
+        let prefix = format!("output.wrap[{}].", i);
+        check_template(
+            format!("{}header", prefix),
+            &TemplateRenderer::default(),
+            None,
+            &w.header,
+            &text,
+            &None,
+            &mut problems,
+        );
+        check_template(
+            format!("{}footer", prefix),
+            &TemplateRenderer::default(),
+            None,
+            &w.footer,
+            &text,
+            &None,
+            &mut problems,
+        );
+    }
+    let mut source_spans = spans.source.into_iter();
+    for (i, source) in config.source.iter().enumerate() {
+        let template_spans = source_spans.next().and_then(|s| s.template);
+        if let Some(templates) = &source.template {
+            let prefix = format!("source[{}].", i);
+            check_templates(&prefix, templates, template_spans, &text, &mut problems);
+        }
+    }
+
+    if problems.is_empty() {
+        println!(
+            "The config file `{}` (version {}) is valid.",
+            path.display(),
+            config.header.version
+        );
+        return Ok(());
+    }
+    let mut msg = format!(
+        "Found {} problem(s) in `{}`:",
+        problems.len(),
+        path.display()
+    );
+    for problem in problems {
+        msg.push_str(&format!("\n - {}", problem));
+    }
+    return Err(SrcDocError::new(msg).with_kind(ErrorKind::Validation));
+}
+
+// Config Migration ////////////////////////////////////////////////////////////////////////
+
+/// The version written by `migrate-config`
+const CONFIG_VERSION: &str = "0.2.1";
+
+/// A change to the config schema: files older than `version` are rewritten by `apply`
+struct Migration {
+    version: &'static str,
+    description: &'static str,
+    apply: fn(&mut toml_edit::DocumentMut) -> bool,
+}
+
+const MIGRATIONS: [Migration; 1] = [Migration {
+    version: "0.2.0",
+    description: "renamed `end` to `stop` in `comment` entries",
+    apply: rename_comment_end,
+}];
+
+/// The `comment` entries of `doc`, including those of its `source` sections
+fn comment_tables(doc: &mut toml_edit::DocumentMut) -> Vec<&mut dyn toml_edit::TableLike> {
+    fn entries(item: Option<&mut toml_edit::Item>) -> Vec<&mut dyn toml_edit::TableLike> {
+        return match item {
+            Some(toml_edit::Item::ArrayOfTables(tables)) => tables
+                .iter_mut()
+                .map(|t| t as &mut dyn toml_edit::TableLike)
+                .collect(),
+            Some(toml_edit::Item::Value(toml_edit::Value::Array(array))) => array
+                .iter_mut()
+                .filter_map(|v| v.as_inline_table_mut())
+                .map(|t| t as &mut dyn toml_edit::TableLike)
+                .collect(),
+            _ => Vec::new(),
+        };
+    }
+    let mut result = Vec::new();
+    for (key, item) in doc.as_table_mut().iter_mut() {
+        match (key.get(), item) {
+            ("comment", item) => result.extend(entries(Some(item))),
+            ("source", toml_edit::Item::ArrayOfTables(sources)) => {
+                for source in sources.iter_mut() {
+                    result.extend(entries(source.get_mut("comment")));
+                }
+            }
+            _ => (),
+        }
+    }
+    return result;
+}
+
+fn rename_comment_end(doc: &mut toml_edit::DocumentMut) -> bool {
+    let mut changed = false;
+    for comment in comment_tables(doc) {
+        if comment.contains_key("stop") {
+            continue;
+        }
+        if let Some(end) = comment.remove("end") {
+            comment.insert("stop", end);
+            changed = true;
+        }
+    }
+    return changed;
+}
+
+/// Rewrites the config file from an older `header.version` to the current schema
+fn migrate_config(args: &Args, print: bool) -> Result<(), SrcDocError> {
+    let path = match &args.config {
+        Some(x) => x.clone(),
+        None => default_config_path(&args.dest),
+    };
+    let text = fs::read_to_string(&path).map_err(|e| SrcDocError::from(e).with_file(&path))?;
+    let format = ConfigFormat::of(&path);
+    // YAML and JSON files are migrated through their TOML equivalent
+    let toml_text = match format {
+        ConfigFormat::Toml => text.clone(),
+        _ => ConfigFormat::Toml.serialize(&format.parse(&text)?)?,
+    };
+    let mut doc: toml_edit::DocumentMut = toml_text.parse().map_err(|e| {
+        return SrcDocError::new(format!("Config Error: {}", e))
+            .with_file(&path)
+            .with_kind(ErrorKind::Config);
+    })?;
+
+    let version = doc
+        .get("header")
+        .and_then(|h| h.get("version"))
+        .and_then(|v| v.as_str())
+        .and_then(|v| Version::parse(v).ok());
+    let Some(version) = version else {
+        let msg = String::from("Config Error: `header.version` is missing or isn't a version");
+        return Err(SrcDocError::new(msg)
+            .with_file(&path)
+            .with_kind(ErrorKind::Config));
+    };
+    let current = Version::parse(CONFIG_VERSION).unwrap();
+    if version > current {
+        return Err(SrcDocError::new(format!(
+            "Config Error: version {} is newer than this version of simple-src-docs ({})",
+            version, current
+        ))
+        .with_file(&path)
+        .with_kind(ErrorKind::Config));
+    }
+    if version == current {
+        println!(
+            "The config file `{}` is already at version {}.",
+            path.display(),
+            current
+        );
+        return Ok(());
+    }
+
+    for migration in &MIGRATIONS {
+        if version < Version::parse(migration.version).unwrap() && (migration.apply)(&mut doc) {
+            info!(
+                "Migrating to {}: {}",
+                migration.version, migration.description
+            );
+        }
+    }
+    doc["header"]["version"] = toml_edit::value(CONFIG_VERSION);
+
+    let migrated = match format {
+        ConfigFormat::Toml => doc.to_string(),
+        _ => {
+            let value: toml::Value = toml::from_str(&doc.to_string())?;
+            format.serialize(&value)?
+        }
+    };
+    if print {
+        print!("{}", migrated);
+        return Ok(());
+    }
+    fs::write(&path, migrated).map_err(|e| SrcDocError::from(e).with_file(&path))?;
+    println!(
+        "Migrated the config file `{}` from version {} to {}.",
+        path.display(),
+        version,
+        current
+    );
+    return Ok(());
+}
+
+// Language Configuration //////////////////////////////////////////////////////////////////
+
+fn str_to_glob<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Glob<'static>, D::Error> {
+    let s: String = Deserialize::deserialize(deserializer)?;
+    return match Glob::new(&case_insensitive(&s)) {
+        Ok(g) => Ok(g.into_owned()),
+        Err(e) => Err(serde::de::Error::custom(e)),
+    }
+}
+
+fn str_to_encoding<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<Option<&'static Encoding>, D::Error> {
+    let s: String = Deserialize::deserialize(deserializer)?;
+    return match Encoding::for_label(s.as_bytes()) {
+        Some(e) => Ok(Some(e)),
+        None => Err(serde::de::Error::custom(format!(
+            "unknown encoding `{}`",
+            s
+        ))),
+    };
+}
+
+/// Reads a regular expression or an array of them
+fn str_to_regexes<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<Regex>, D::Error> {
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum OneOrMany {
+        One(String),
+        Many(Vec<String>),
+    }
+    let patterns = match OneOrMany::deserialize(deserializer)? {
+        OneOrMany::One(x) => vec![x],
+        OneOrMany::Many(x) => x,
+    };
+    return patterns
+        .iter()
+        .map(|p| Regex::new(p).map_err(serde::de::Error::custom))
+        .collect();
+}
+
+/// Reads a regular expression or an array of alternatives for it, which are joined into one;
+/// the text of a match is in the first capture group that took part in it (see `first_group`)
+fn str_to_alternatives<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<Option<Regex>, D::Error> {
+    let patterns = str_to_regexes(deserializer)?;
+    let pattern = match patterns.as_slice() {
+        [] => return Ok(None),
+        [regex] => String::from(regex.as_str()),
+        _ => {
+            let alternatives: Vec<_> = patterns.iter().map(|r| format!("(?:{})", r)).collect();
+            alternatives.join("|")
+        }
+    };
+    return Regex::new(&pattern)
+        .map(Some)
+        .map_err(serde::de::Error::custom);
+}
+
+/// The first capture group that took part in a match (e.g. of one of the alternatives read
+/// by `str_to_alternatives`)
+fn first_group<'t>(capture: &regex::Captures<'t>) -> Option<regex::Match<'t>> {
+    return capture.iter().skip(1).flatten().next();
+}
+
+fn regexes_to_str<S: serde::Serializer>(s: &[Regex], serializer: S) -> Result<S::Ok, S::Error> {
+    return match s {
+        [regex] => serializer.serialize_str(regex.as_str()),
+        _ => serializer.collect_seq(s.iter().map(Regex::as_str)),
+    };
+}
+
+fn encoding_to_str<S: serde::Serializer>(
+    s: &Option<&'static Encoding>,
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    return match s {
+        Some(encoding) => serializer.serialize_str(encoding.name()),
+        None => serializer.serialize_none(),
+    };
+}
+
+fn glob_to_str<S: serde::Serializer>(s: &Glob, serializer: S) -> Result<S::Ok, S::Error> {
+    return serializer.serialize_str(&case_sensitive(&s.to_string()));
+}
+
+/// Adds the case-insensitive flag to the glob `s`, at its start or else after a leading
+/// `**/`, which can't follow the flag
+fn case_insensitive(s: &str) -> String {
+    return match s.strip_prefix("**/") {
+        Some(rest) => format!("**/(?i){}", rest),
+        None => format!("(?i){}", s),
+    };
+}
+
+/// The glob given to `case_insensitive` for `s`: only the flag that it added is removed, so
+/// that one written in the config file is kept
+fn case_sensitive(s: &str) -> String {
+    if let Some(rest) = s.strip_prefix("**/(?i)") {
+        return format!("**/{}", rest);
+    }
+    return String::from(s.strip_prefix("(?i)").unwrap_or(s));
+}
+
+fn str_to_opt_glob<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<Option<Glob<'static>>, D::Error> {
+    return Ok(Some(str_to_glob(deserializer)?));
+}
+
+fn opt_glob_to_str<S: serde::Serializer>(
+    s: &Option<Glob>,
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    return match s {
+        Some(glob) => glob_to_str(glob, serializer),
+        None => serializer.serialize_none(),
+    };
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+#[serde(deny_unknown_fields)]
+struct CommentConfig {
+    #[serde(default = "zero")]
+    order: f64,
+    /// true for the entries of `DEFAULT_COMMENT_MAP`, which user entries always take
+    /// precedence over
+    #[serde(skip)]
+    builtin: bool,
+    #[serde(deserialize_with = "str_to_glob", serialize_with = "glob_to_str")]
+    extension: Glob<'static>,
+    /// if given, the entry only applies to files whose path (or a trailing part of it)
+    /// matches this glob, e.g. `tests/**`
+    #[serde(
+        default,
+        deserialize_with = "str_to_opt_glob",
+        serialize_with = "opt_glob_to_str",
+        skip_serializing_if = "Option::is_none"
+    )]
+    path: Option<Glob<'static>>,
+    /// given several patterns (e.g. for `/**` and `/*!`), a line matching any of them counts
+    #[serde(
+        default,
+        deserialize_with = "str_to_alternatives",
+        serialize_with = "serde_regex::serialize"
+    )]
+    start: Option<Regex>,
+    #[serde(
+        default,
+        deserialize_with = "str_to_alternatives",
+        serialize_with = "serde_regex::serialize"
+    )]
+    each_line: Option<Regex>,
+    #[serde(default, with = "serde_regex")]
+    stop: Option<Regex>,
+    /// overrides the top-level `tag_pattern` for these files; given several patterns, each
+    /// line uses the first one that matches it
+    #[serde(
+        default,
+        deserialize_with = "str_to_regexes",
+        serialize_with = "regexes_to_str",
+        skip_serializing_if = "Vec::is_empty"
+    )]
+    tag_pattern: Vec<Regex>,
+    /// the encoding of these files (UTF-8 if not given), e.g. `latin1` or `shift_jis`
+    #[serde(
+        default,
+        deserialize_with = "str_to_encoding",
+        serialize_with = "encoding_to_str",
+        skip_serializing_if = "Option::is_none"
+    )]
+    encoding: Option<&'static Encoding>,
+    /// a pattern for the definition a block documents, e.g. a python `def` line, made
+    /// available to templates as `__signature__`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    signature: Option<SignatureConfig>,
+    /// for languages whose block comments nest, the opener of a nested comment (e.g. `#=`):
+    /// the comment only ends at the `stop` matching its own `start`
+    #[serde(default, with = "serde_regex", skip_serializing_if = "Option::is_none")]
+    nested_start: Option<Regex>,
+    /// reads docs written after code on the same line, e.g. `int x; ///< doc`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    inline: Option<InlineConfig>,
+    /// for single-line comments, what can come between comments that are still read as one
+    /// block; by default, a comment ends at the first line without the comment syntax
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    merge: Option<CommentMerge>,
+}
+
+/// The lines between two single-line comments that are merged into one block
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+enum CommentMerge {
+    /// only blank lines
+    Blank,
+    /// blank lines and at most one other line, such as an attribute
+    Line,
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+#[serde(deny_unknown_fields)]
+struct InlineConfig {
+    /// matched against each line outside of the comments; the first capture group is the doc
+    #[serde(with = "serde_regex")]
+    pattern: Regex,
+    /// add the doc to the body of the closest block above it, rather than making it a block
+    /// of its own
+    #[serde(default)]
+    attach: bool,
+}
+
+/// Where the definition documented by a block is found
+#[derive(Serialize, Deserialize, Default, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+enum SignaturePosition {
+    #[default]
+    Before,
+    After,
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+#[serde(deny_unknown_fields)]
+struct SignatureConfig {
+    /// matched against the closest non-blank line before (or after) the comment; the first
+    /// capture group, if any, is the signature, otherwise the whole line is
+    #[serde(with = "serde_regex")]
+    pattern: Regex,
+    #[serde(default)]
+    position: SignaturePosition,
+    /// how many lines after the comment a signature can span (e.g. the parameters of a
+    /// function given one per line); they are joined until `trim` matches
+    #[serde(default = "one_line")]
+    lines: usize,
+    /// where the signature ends, e.g. at the `{` of a function body; any text from its first
+    /// match outside of brackets on is left out
+    #[serde(default, with = "serde_regex", skip_serializing_if = "Option::is_none")]
+    trim: Option<Regex>,
+    /// lines passed over when looking for the signature, such as attributes
+    #[serde(default, with = "serde_regex", skip_serializing_if = "Option::is_none")]
+    skip: Option<Regex>,
+}
+
+impl CommentConfig {
+    /// The signature of the block read from the comment spanning the (1-based) lines `span`
+    /// of `lines`, if `signature` is set and the line next to the comment matches it
+    fn find_signature(&self, lines: &[&str], span: (usize, usize)) -> Option<String> {
+        let signature = self.signature.as_ref()?;
+        let candidate = |l: &&str| {
+            return !l.trim().is_empty() && !signature.skip.as_ref().is_some_and(|s| s.is_match(l));
+        };
+        let mut text = match signature.position {
+            SignaturePosition::Before => lines
+                .iter()
+                .take(span.0.saturating_sub(1))
+                .rev()
+                .find(|l| candidate(l))
+                .map(|l| l.to_string())?,
+            SignaturePosition::After => {
+                let start = span.1 + lines.iter().skip(span.1).position(candidate)?;
+                let following: Vec<_> = lines[start..]
+                    .iter()
+                    .take(signature.lines.max(1))
+                    .map(|l| l.trim())
+                    .collect();
+                // join the lines up to the one where `trim` matches, if it does
+                let joined = following.join(" ");
+                match signature
+                    .trim
+                    .as_ref()
+                    .and_then(|t| find_unbracketed(t, &joined))
+                {
+                    Some(_) => joined,
+                    None => following[0].to_string(),
+                }
+            }
+        };
+        if let Some(end) = signature
+            .trim
+            .as_ref()
+            .and_then(|t| find_unbracketed(t, &text))
+        {
+            text.truncate(end);
+        }
+        let capture = signature.pattern.captures(&text)?;
+        let text = capture.get(1).or(capture.get(0))?.as_str();
+        return Some(String::from(text.trim()));
+    }
+
+    fn matches(&self, file: &Path) -> bool {
+        let name_matches = self.extension.is_match(file)
+            || file
+                .file_name()
+                .is_some_and(|name| self.extension.is_match(name));
+        let Some(path) = &self.path else {
+            return name_matches;
+        };
+        // compare against each trailing part of the path, so that `tests/**` matches
+        // `src/tests/a.py` whichever directory the source files are read from
+        let file = without_cur_dir(file);
+        let parts: Vec<_> = file.components().collect();
+        return name_matches
+            && (0..parts.len())
+                .any(|i| path.is_match(parts[i..].iter().collect::<PathBuf>().as_path()));
+    }
+}
+
+/// The start of the first match of `trim` in `text` that isn't inside `()`, `[]` or `<>`,
+/// such as the `;` ending `fn f(x: [u8; 4]);` rather than the one in its array type
+fn find_unbracketed(trim: &Regex, text: &str) -> Option<usize> {
+    let mut depths = Vec::with_capacity(text.len());
+    let mut depth: usize = 0;
+    let mut previous = ' ';
+    for c in text.chars() {
+        depths.extend(std::iter::repeat_n(depth, c.len_utf8()));
+        match c {
+            '(' | '[' | '<' => depth += 1,
+            // the `>` of `->` and `=>` doesn't close a bracket
+            '>' if previous == '-' || previous == '=' => (),
+            ')' | ']' | '>' => depth = depth.saturating_sub(1),
+            _ => (),
+        }
+        previous = c;
+    }
+    return trim
+        .find_iter(text)
+        .map(|m| m.start())
+        .find(|start| depths[*start] == 0);
+}
+
+lazy_static! {
+    /// the `def` or `class` line a python docstring belongs to, without its trailing `:`
+    static ref PYTHON_SIGNATURE: Regex =
+        Regex::new(r"^\s*((?:async\s+)?(?:def|class)\s.*?):?\s*(?:#.*)?$").unwrap();
+    /// the item a rust doc comment belongs to, e.g. `pub fn parse(s: &str) -> u8`
+    static ref RUST_SIGNATURE: Regex = Regex::new(concat!(
+        r"^(?:pub(?:\([^)]*\))?\s+)?(?:(?:async|const|unsafe|extern\s+\S+)\s+)*",
+        r"(?:fn|struct|enum|union|trait|type|const|static|mod|impl|macro_rules!)\b.*"
+    ))
+    .unwrap();
+    /// the language of a `@snippet`'s code block, for the file extensions that don't name it
+    static ref FENCE_LANGUAGES: Vec<(&'static str, &'static str)> = vec![
+        ("rs", "rust"),
+        ("py", "python"),
+        ("jl", "julia"),
+        ("rb", "ruby"),
+        ("hs", "haskell"),
+        ("ex", "elixir"),
+        ("exs", "elixir"),
+        ("ml", "ocaml"),
+        ("kt", "kotlin"),
+        ("cs", "csharp"),
+        ("sh", "bash"),
+        ("ps1", "powershell"),
+        ("yml", "yaml"),
+        ("md", "markdown"),
+    ];
+    /// the default `tag_pattern`, matching `@tag value`
+    static ref DEFAULT_TAG_PATTERN: Regex = Regex::new(r".*@(?<tag>\S+)\s+(?<value>.*)").unwrap();
+    /// the keywords of PowerShell comment-based help (e.g. `.SYNOPSIS`), along with `@` tags
+    static ref POWERSHELL_TAG_PATTERNS: Vec<Regex> = vec![
+        Regex::new(concat!(
+            r"^\s*\.(?<tag>(?i:SYNOPSIS|DESCRIPTION|PARAMETER|EXAMPLE|INPUTS|OUTPUTS|NOTES|LINK",
+            r"|COMPONENT|ROLE|FUNCTIONALITY))\b\s*(?<value>.*)$",
+        ))
+        .unwrap(),
+        DEFAULT_TAG_PATTERN.clone(),
+    ];
+}
+
+/// The file extension used for the comment syntax of scripts run by each interpreter
+const DEFAULT_SHEBANG_MAP: [(&str, &str); 16] = [
+    ("sh", "sh"),
+    ("bash", "sh"),
+    ("zsh", "sh"),
+    ("dash", "sh"),
+    ("ksh", "sh"),
+    ("python", "py"),
+    ("ruby", "rb"),
+    ("perl", "pl"),
+    ("node", "js"),
+    ("deno", "ts"),
+    ("julia", "jl"),
+    ("Rscript", "r"),
+    ("lua", "lua"),
+    ("tclsh", "tcl"),
+    ("awk", "awk"),
+    ("php", "php"),
+];
+
+/// A pattern matching the text that doesn't start with any of `words`, since regexes can't
+/// look ahead, e.g. `(?:$|[^a].*|a(?:$|[^b].*))` for `ab`
+fn not_starting_with(words: &[&str]) -> String {
+    let mut firsts: Vec<char> = words.iter().filter_map(|w| w.chars().next()).collect();
+    firsts.sort();
+    firsts.dedup();
+    let class: String = firsts
+        .iter()
+        .map(|c| regex::escape(&c.to_string()))
+        .collect();
+    let mut pattern = format!("(?:$|[^{}].*", class);
+    for first in firsts {
+        let rests: Vec<&str> = words.iter().filter_map(|w| w.strip_prefix(first)).collect();
+        // a text starting with a whole word doesn't match
+        if !rests.contains(&"") {
+            let escaped = regex::escape(&first.to_string());
+            pattern.push_str(&format!("|{}{}", escaped, not_starting_with(&rests)));
+        }
+    }
+    pattern.push(')');
+    return pattern;
+}
+
+lazy_static! {
+    static ref DEFAULT_COMMENT_MAP: Vec<CommentConfig> = vec![
+        CommentConfig {
+            order: 0.0,
+            builtin: true,
+            extension: Glob::new("(?i)*.{c,cpp,java,h,hpp,c++,h++,cxx,hxx,groovy,v,js,cs,ts,jsx,tsx,go,zig,kt,kts,d,swift,php,css,scala,dart}").unwrap(),
+            path: None,
+            // the Doxygen forms `/**`, `/*!` and `/**<`
+            // the text of `/** One-liner. */` and `/** Summary` follows the opener
+            start: Some(Regex::new(r"^\s*/\*[*!]<?(?:\s*$|\s+(.*)$)").unwrap()),
+            each_line: Some(Regex::new(r"^\s*\*\s?(.*)").unwrap()),
+            stop: Some(Regex::new(r"\s*\*/").unwrap()),
+            tag_pattern: Vec::new(),
+            encoding: None,
+            signature: None,
+            nested_start: None,
+            inline: None,
+            merge: None,
+        },
+        CommentConfig {
+            order: 0.0,
+            builtin: true,
+            extension: Glob::new("(?i)*.{c,cpp,java,h,hpp,c++,h++,cxx,hxx,groovy,v,js,cs,ts,jsx,tsx,go,zig,kt,kts,d,swift,php,css,scala,dart}").unwrap(),
+            path: None,
+            start: None,
+            // Doxygen's `///` and `//!` lines, but not TypeScript's `/// <reference ... />` and
+            // `/// <amd-module ... />` directives or `///<` docs of the code before them
+            each_line: Some(Regex::new(&format!(
+                r"^\s*//[/!](?:\s?(\s*[^\s<].*)|\s(\s*<{})|\s*())$",
+                not_starting_with(&["reference", "amd-"])
+            )).unwrap()),
+            stop: None,
+            tag_pattern: Vec::new(),
+            encoding: None,
+            signature: None,
+            nested_start: None,
+            inline: None,
+            merge: None,
+        },
+        CommentConfig {
+            order: 0.0,
+            builtin: true,
+            extension: Glob::new("(?i)*.{rb,r,pl,pm,awk,nim,crystal,tcl}").unwrap(),
+            path: None,
+            start: None,
+            // `#` lines, but not a `#!` line naming the interpreter
+            each_line: Some(Regex::new(r"^\s*#(?:\s(.*)|([^!].*)|())$").unwrap()),
+            stop: None,
+            tag_pattern: Vec::new(),
+            encoding: None,
+            signature: None,
+            nested_start: None,
+            inline: None,
+            merge: None,
+        },
+        CommentConfig {
+            order: 0.0,
+            builtin: true,
+            extension: Glob::new("(?i)*.sh").unwrap(),
+            path: None,
+            start: None,
+            // `#` lines, but not a `#!` line naming the interpreter
+            each_line: Some(Regex::new(r"^\s*#(?:\s(.*)|([^!].*)|())$").unwrap()),
+            stop: None,
+            tag_pattern: Vec::new(),
+            encoding: None,
+            signature: None,
+            nested_start: None,
+            inline: None,
+            merge: None,
+        },
+        CommentConfig {
+            order: 0.0,
+            builtin: true,
+            extension: Glob::new("(?i)*.sh").unwrap(),
+            path: None,
+            // the here-document idiom `: <<'DOCS'`, which the `:` command ignores
+            start: Some(Regex::new(r#"^\s*:\s*<<-?\s*(?:'DOCS?'|"DOCS?"|DOCS?)\s*$"#).unwrap()),
+            each_line: None,
+            stop: Some(Regex::new(r"^\s*DOCS?\s*$").unwrap()),
+            tag_pattern: Vec::new(),
+            encoding: None,
+            signature: None,
+            nested_start: None,
+            inline: None,
+            merge: None,
+        },
+        CommentConfig {
+            order: 1.0,
+            builtin: true,
+            extension: Glob::new("(?i)*.{asm,s,clj,el,lisp,scm,ss,rkt}").unwrap(),
+            path: None,
+            start: None,
+            each_line: Some(Regex::new(r"^\s*;\s?(.*)$").unwrap()),
+            stop: None,
+            tag_pattern: Vec::new(),
+            encoding: None,
+            signature: None,
+            nested_start: None,
+            inline: None,
+            merge: None,
+        },
+        CommentConfig {
+            order: 1.0,
+            builtin: true,
+            extension: Glob::new("(?i)*.{vb,vba}").unwrap(),
+            path: None,
+            start: None,
+            each_line: Some(Regex::new(r"^\s*'\s?(.*)$").unwrap()),
+            stop: None,
+            tag_pattern: Vec::new(),
+            encoding: None,
+            signature: None,
+            nested_start: None,
+            inline: None,
+            merge: None,
+        },
+        CommentConfig {
+            order: 1.0,
+            builtin: true,
+            extension: Glob::new("(?i)*.{f,for,f90,f95,fortran}").unwrap(),
+            path: None,
+            start: None,
+            each_line: Some(Regex::new(r"^\s*!\s?(.*)$").unwrap()),
+            stop: None,
+            tag_pattern: Vec::new(),
+            encoding: None,
+            signature: None,
+            nested_start: None,
+            inline: None,
+            merge: None,
+        },
+        CommentConfig {
+            order: 0.0,
+            builtin: true,
+            extension: Glob::new("(?i)*.{hs,elm}").unwrap(),
+            path: None,
+            start: None,
+            each_line: Some(Regex::new(r"^\s*--\s?(.*)$").unwrap()),
+            stop: None,
+            tag_pattern: Vec::new(),
+            encoding: None,
+            signature: None,
+            nested_start: None,
+            inline: None,
+            merge: None,
+        },
+        CommentConfig {
+            order: 0.0,
+            builtin: true,
+            extension: Glob::new("(?i)*.{hs,elm}").unwrap(),
+            path: None,
+            // `{-` and the haddock `{-|`, but not pragmas such as `{-# LANGUAGE ... #-}`
+            start: Some(Regex::new(r"^\s*\{-(?:\|\s*|\s+|$)(.*)$").unwrap()),
+            each_line: None,
+            stop: Some(Regex::new(r"\s*-\}").unwrap()),
+            tag_pattern: Vec::new(),
+            encoding: None,
+            signature: None,
+            nested_start: Some(Regex::new(r"\{-").unwrap()),
+            inline: None,
+            merge: None,
+        },
+        CommentConfig {
+            order: 0.0,
+            builtin: true,
+            extension: Glob::new("(?i)*.sql").unwrap(),
+            path: None,
+            start: None,
+            each_line: Some(Regex::new(r"^\s*--\s?(.*)$").unwrap()),
+            stop: None,
+            tag_pattern: Vec::new(),
+            encoding: None,
+            signature: None,
+            nested_start: None,
+            inline: None,
+            merge: None,
+        },
+        CommentConfig {
+            order: 0.0,
+            builtin: true,
+            extension: Glob::new("(?i)*.lua").unwrap(),
+            path: None,
+            start: None,
+            // `--` comments, other than the opening `--[[` (or `--[==[`) of block comments
+            each_line: Some(
+                Regex::new(r"^\s*--\s?((?:[^\[]|\[=*[^=\[]|\[=*$).*|)$").unwrap(),
+            ),
+            stop: None,
+            tag_pattern: Vec::new(),
+            encoding: None,
+            signature: None,
+            nested_start: None,
+            inline: None,
+            merge: None,
+        },
+        CommentConfig {
+            order: 0.0,
+            builtin: true,
+            extension: Glob::new("(?i)*.{py,pyi}").unwrap(),
+            path: None,
+            start: Some(Regex::new(r#"^\s*"""(.*)$"#).unwrap()),
+            each_line: None,
+            stop: Some(Regex::new(r#""""\s*$"#).unwrap()),
+            tag_pattern: Vec::new(),
+            encoding: None,
+            signature: Some(SignatureConfig {
+                pattern: PYTHON_SIGNATURE.clone(),
+                position: SignaturePosition::Before,
+                lines: 1,
+                trim: None,
+                skip: None,
+            }),
+            nested_start: None,
+            inline: None,
+            merge: None,
+        },
+        CommentConfig {
+            order: 0.0,
+            builtin: true,
+            extension: Glob::new("(?i)*.{py,pyi}").unwrap(),
+            path: None,
+            start: Some(Regex::new(r"^\s*'''(.*)$").unwrap()),
+            each_line: None,
+            stop: Some(Regex::new(r"'''\s*$").unwrap()),
+            tag_pattern: Vec::new(),
+            encoding: None,
+            signature: Some(SignatureConfig {
+                pattern: PYTHON_SIGNATURE.clone(),
+                position: SignaturePosition::Before,
+                lines: 1,
+                trim: None,
+                skip: None,
+            }),
+            nested_start: None,
+            inline: None,
+            merge: None,
+        },
+        CommentConfig {
+            order: 0.0,
+            builtin: true,
+            extension: Glob::new("(?i)*.rs").unwrap(),
+            path: None,
+            start: None,
+            each_line: Some(Regex::new(r"^\s*///\s?(.*)$").unwrap()),
+            stop: None,
+            tag_pattern: Vec::new(),
+            encoding: None,
+            signature: Some(SignatureConfig {
+                pattern: RUST_SIGNATURE.clone(),
+                position: SignaturePosition::After,
+                lines: 8,
+                trim: Some(Regex::new(r"\s*[{;]").unwrap()),
+                skip: Some(Regex::new(r"^\s*#\[").unwrap()),
+            }),
+            nested_start: None,
+            inline: None,
+            merge: None,
+        },
+        CommentConfig {
+            order: 0.0,
+            builtin: true,
+            extension: Glob::new("(?i)*.rs").unwrap(),
+            path: None,
+            start: None,
+            each_line: Some(Regex::new(r"^\s*//!\s?(.*)$").unwrap()),
+            stop: None,
+            tag_pattern: Vec::new(),
+            encoding: None,
+            signature: None,
+            nested_start: None,
+            inline: None,
+            merge: None,
+        },
+        CommentConfig {
+            order: 0.0,
+            builtin: true,
+            extension: Glob::new("(?i)*.rs").unwrap(),
+            path: None,
+            // the `/** ... */` and `/*! ... */` doc blocks, in which comments can nest
+            start: Some(Regex::new(r"^\s*/\*[*!](?:\s*$|\s+(.*)$)").unwrap()),
+            each_line: Some(Regex::new(r"^\s*\*\s?(.*)").unwrap()),
+            stop: Some(Regex::new(r"\s*\*/").unwrap()),
+            tag_pattern: Vec::new(),
+            encoding: None,
+            signature: None,
+            nested_start: Some(Regex::new(r"/\*").unwrap()),
+            inline: None,
+            merge: None,
+        },
+        CommentConfig {
+            order: 0.0,
+            builtin: true,
+            extension: Glob::new("(?i)*.jl").unwrap(),
+            path: None,
+            start: None,
+            // `#` lines, but not the `#=` openers of block comments or a `#!` line
+            each_line: Some(Regex::new(r"^\s*#(?:\s(.*)|([^=!].*)|())$").unwrap()),
+            stop: None,
+            tag_pattern: Vec::new(),
+            encoding: None,
+            signature: None,
+            nested_start: None,
+            inline: None,
+            merge: None,
+        },
+        CommentConfig {
+            order: 0.0,
+            builtin: true,
+            extension: Glob::new("(?i)*.jl").unwrap(),
+            path: None,
+            start: Some(Regex::new(r"^\s*#=(?:\s*$|\s+(.*)$)").unwrap()),
+            each_line: None,
+            stop: Some(Regex::new(r"\s*=#").unwrap()),
+            tag_pattern: Vec::new(),
+            encoding: None,
+            signature: None,
+            nested_start: Some(Regex::new(r"#=").unwrap()),
+            inline: None,
+            merge: None,
+        },
+        CommentConfig {
+            order: 0.0,
+            builtin: true,
+            extension: Glob::new("(?i)*.{ml,mli}").unwrap(),
+            path: None,
+            // `(** ... *)` doc comments; `(* ... *)` comments are left out
+            start: Some(Regex::new(r"^\s*\(\*\*(?:\s+|$)(.*)$").unwrap()),
+            each_line: None,
+            stop: Some(Regex::new(r"\s*\*\)").unwrap()),
+            tag_pattern: Vec::new(),
+            encoding: None,
+            signature: None,
+            nested_start: Some(Regex::new(r"\(\*").unwrap()),
+            inline: None,
+            merge: None,
+        },
+        CommentConfig {
+            order: 0.0,
+            builtin: true,
+            extension: Glob::new("(?i)*.{pas,pp}").unwrap(),
+            path: None,
+            start: Some(Regex::new(r"^\s*\(\*\*(?:\s+|$)(.*)$").unwrap()),
+            each_line: None,
+            stop: Some(Regex::new(r"\s*\*\)").unwrap()),
+            tag_pattern: Vec::new(),
+            encoding: None,
+            signature: None,
+            nested_start: None,
+            inline: None,
+            merge: None,
+        },
+        // `.m` is used by both Objective-C and MATLAB: read the comments of either language
+        CommentConfig {
+            order: 0.0,
+            builtin: true,
+            extension: Glob::new("(?i)*.m").unwrap(),
+            path: None,
+            start: Some(Regex::new(r"^\s*/\*\*(?:\s*$|\s+(.*)$)").unwrap()),
+            each_line: Some(Regex::new(r"^\s*\*\s?(.*)").unwrap()),
+            stop: Some(Regex::new(r"\s*\*/").unwrap()),
+            tag_pattern: Vec::new(),
+            encoding: None,
+            signature: None,
+            nested_start: None,
+            inline: None,
+            merge: None,
+        },
+        CommentConfig {
+            order: 0.0,
+            builtin: true,
+            extension: Glob::new("(?i)*.m").unwrap(),
+            path: None,
+            start: None,
+            // `%` comments, other than the `%{` and `%}` lines of block comments
+            each_line: Some(Regex::new(r"^\s*%\s?((?:[^{}]|[{}]\s*\S).*|)$").unwrap()),
+            stop: None,
+            tag_pattern: Vec::new(),
+            encoding: None,
+            signature: None,
+            nested_start: None,
+            inline: None,
+            merge: None,
+        },
+        CommentConfig {
+            order: 0.0,
+            builtin: true,
+            extension: Glob::new("(?i)*.m").unwrap(),
+            path: None,
+            start: Some(Regex::new(r"^\s*%\{\s*$").unwrap()),
+            each_line: None,
+            stop: Some(Regex::new(r"^\s*%\}\s*$").unwrap()),
+            tag_pattern: Vec::new(),
+            encoding: None,
+            signature: None,
+            nested_start: None,
+            inline: None,
+            merge: None,
+        },
+        CommentConfig {
+            order: 0.0,
+            builtin: true,
+            extension: Glob::new("(?i)*.{ps1,psm1,psd1}").unwrap(),
+            path: None,
+            start: None,
+            // `#` comments, other than the closing `#>` of block comments or a `#!` line
+            each_line: Some(Regex::new(r"^\s*#(?:\s([^>].*|)|([^>!].*)|())$").unwrap()),
+            stop: None,
+            tag_pattern: POWERSHELL_TAG_PATTERNS.clone(),
+            encoding: None,
+            signature: None,
+            nested_start: None,
+            inline: None,
+            merge: None,
+        },
+        CommentConfig {
+            order: 0.0,
+            builtin: true,
+            extension: Glob::new("(?i)*.{ps1,psm1,psd1}").unwrap(),
+            path: None,
+            start: Some(Regex::new(r"^\s*<#\s*(.*)$").unwrap()),
+            each_line: None,
+            stop: Some(Regex::new(r"\s*#>").unwrap()),
+            tag_pattern: POWERSHELL_TAG_PATTERNS.clone(),
+            encoding: None,
+            signature: None,
+            nested_start: None,
+            inline: None,
+            merge: None,
+        },
+        // configuration files
+        CommentConfig {
+            order: 0.0,
+            builtin: true,
+            extension: Glob::new("(?i)*.{toml,yaml,yml,env}").unwrap(),
+            path: None,
+            start: None,
+            each_line: Some(Regex::new(r"^\s*#(?:\s(.*)|([^!].*)|())$").unwrap()),
+            stop: None,
+            tag_pattern: Vec::new(),
+            encoding: None,
+            signature: None,
+            nested_start: None,
+            inline: None,
+            merge: None,
+        },
+        CommentConfig {
+            order: 0.0,
+            builtin: true,
+            extension: Glob::new("(?i)*.ini").unwrap(),
+            path: None,
+            start: None,
+            each_line: Some(Regex::new(r"^\s*#(?:\s(.*)|([^!].*)|())$").unwrap()),
+            stop: None,
+            tag_pattern: Vec::new(),
+            encoding: None,
+            signature: None,
+            nested_start: None,
+            inline: None,
+            merge: None,
+        },
+        CommentConfig {
+            order: 0.0,
+            builtin: true,
+            extension: Glob::new("(?i)*.ini").unwrap(),
+            path: None,
+            start: None,
+            each_line: Some(Regex::new(r"^\s*;\s?(.*)$").unwrap()),
+            stop: None,
+            tag_pattern: Vec::new(),
+            encoding: None,
+            signature: None,
+            nested_start: None,
+            inline: None,
+            merge: None,
+        },
+        CommentConfig {
+            order: 0.0,
+            builtin: true,
+            extension: Glob::new("(?i)*.{ex,exs}").unwrap(),
+            path: None,
+            // the heredocs of `@moduledoc`, `@doc` and `@typedoc` attributes
+            start: Some(
+                Regex::new(r#"^\s*@(?:moduledoc|typedoc|doc)\s+(?:~[sS])?"""\s*$"#).unwrap(),
+            ),
+            each_line: None,
+            stop: Some(Regex::new(r#"^\s*"""\s*$"#).unwrap()),
+            tag_pattern: Vec::new(),
+            encoding: None,
+            signature: None,
+            nested_start: None,
+            inline: None,
+            merge: None,
+        },
+        CommentConfig {
+            order: 0.0,
+            builtin: true,
+            extension: Glob::new("(?i)*.{html,htm,xml,xhtml,svg,vue,svelte}").unwrap(),
+            path: None,
+            start: Some(Regex::new(r"^\s*<!--\s*(.*)$").unwrap()),
+            each_line: None,
+            stop: Some(Regex::new(r"\s*-->").unwrap()),
+            tag_pattern: Vec::new(),
+            encoding: None,
+            signature: None,
+            nested_start: None,
+            inline: None,
+            merge: None,
+        },
+    ]
+    .into_iter()
+    .chain((0..=LUA_LONG_BRACKET_LEVELS).map(lua_block_comment))
+    .chain([latex_comment(false)])
+    .collect();
+}
+
+/// The syntax of TeX and BibTeX comments: `%` lines, or only `%%` lines with `doc_marker`
+/// (see `LatexConfig`)
+fn latex_comment(doc_marker: bool) -> CommentConfig {
+    let each_line = match doc_marker {
+        true => r"^\s*%%\s?(.*)$",
+        false => r"^\s*%\s?(.*)$",
+    };
+    return CommentConfig {
+        order: 0.0,
+        builtin: true,
+        extension: Glob::new("(?i)*.{tex,sty,cls,bib}").unwrap(),
+        path: None,
+        start: None,
+        each_line: Some(Regex::new(each_line).unwrap()),
+        stop: None,
+        tag_pattern: Vec::new(),
+        encoding: None,
+        signature: None,
+        nested_start: None,
+        inline: None,
+        merge: None,
+    };
+}
+
+/// The highest level of Lua long brackets (the number of `=` in `--[==[`) that the built-in
+/// comment syntaxes read
+const LUA_LONG_BRACKET_LEVELS: usize = 3;
+
+/// The syntax of Lua block comments whose long brackets have the given `level`; block
+/// comments only close with brackets of their own level, so each level is its own entry
+fn lua_block_comment(level: usize) -> CommentConfig {
+    let equals = "=".repeat(level);
+    return CommentConfig {
+        order: 0.0,
+        builtin: true,
+        extension: Glob::new("(?i)*.lua").unwrap(),
+        path: None,
+        start: Some(Regex::new(&format!(r"^\s*--\[{}\[\s*(.*)$", equals)).unwrap()),
+        each_line: None,
+        stop: Some(Regex::new(&format!(r"\s*(?:--)?\]{}\]", equals)).unwrap()),
+        tag_pattern: Vec::new(),
+        encoding: None,
+        signature: None,
+        nested_start: None,
+        inline: None,
+        merge: None,
+    };
+}
+
+// Templates ///////////////////////////////////////////////////////////////////////////////
+
+fn tag_groups(tag_r: &Regex) -> Result<(), ValidationError> {
+    let names: Vec<_> = tag_r.capture_names().flatten().collect();
+    if !names.contains(&"tag") || !names.contains(&"value") {
+        return Err(ValidationError::new(
+            "tag patterns must have capture groups named `tag` and `value`.",
+        ));
+    }
+    return Ok(());
+}
+
+fn config_tag_groups(config: &SrcDocConfig) -> Result<(), ValidationError> {
+    if let Some(tag_r) = &config.tag_pattern {
+        tag_groups(tag_r).map_err(|e| {
+            let msg = e.message.as_ref().unwrap_or(&e.code);
+            return ValidationError::new("tag_pattern")
+                .with_message(format!("`tag_pattern`: {}", msg).into());
+        })?;
+    }
+    if let Some(prefix) = &config.tag_prefix {
+        if prefix.is_empty() || prefix.contains(char::is_whitespace) {
+            return Err(ValidationError::new("tag_prefix").with_message(
+                "`tag_prefix`: must be non-empty and can't contain whitespace.".into(),
+            ));
+        }
+    }
+    return Ok(());
+}
+
+fn comment_tag_groups(comment: &[CommentConfig]) -> Result<(), ValidationError> {
+    for tag_r in comment.iter().flat_map(|c| &c.tag_pattern) {
+        tag_groups(tag_r)?;
+    }
+    return Ok(());
+}
+
+fn start_stop_match(comment: &Vec<CommentConfig>) -> Result<(), ValidationError> {
+    for c in comment {
+        if c.start.is_none() ^ c.stop.is_none() {
+            return Err(ValidationError::new(
+                "start and stop must both be present, or they must both be absent.",
+            ));
+        }
+    }
+    return Ok(());
+}
+
+fn inline_groups(comment: &[CommentConfig]) -> Result<(), ValidationError> {
+    for inline in comment.iter().filter_map(|c| c.inline.as_ref()) {
+        if inline.pattern.captures_len() < 2 {
+            return Err(ValidationError::new(
+                "inline patterns must have a capture group for the doc.",
+            ));
+        }
+    }
+    return Ok(());
+}
+
+#[derive(Serialize, Deserialize, Validate)]
+#[serde(deny_unknown_fields)]
+#[validate(schema(function = "config_tag_groups"))]
+struct SrcDocConfig {
+    #[validate(nested)]
+    header: ConfigHeader,
+    #[serde(default)]
+    template: Option<ConfigTemplates>,
+    #[serde(default)]
+    #[validate(
+        custom(function = "start_stop_match"),
+        custom(function = "comment_tag_groups"),
+        custom(function = "inline_groups")
+    )]
+    comment: Option<Vec<CommentConfig>>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    #[validate(nested)]
+    source: Vec<SourceConfig>,
+    /// matches a comment line that starts a tag, with the capture groups `tag` and `value`;
+    /// defaults to `DEFAULT_TAG_PATTERN`
+    #[serde(default, with = "serde_regex", skip_serializing_if = "Option::is_none")]
+    tag_pattern: Option<Regex>,
+    /// the character(s) that mark a tag in place of `@`, only at the start of a comment line;
+    /// ignored when `tag_pattern` is given
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    tag_prefix: Option<String>,
+    /// maps the interpreter of a `#!` line to the file extension whose comment syntax is
+    /// used for files that match no `comment` entry, on top of `DEFAULT_SHEBANG_MAP`
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    shebang: BTreeMap<String, String>,
+    /// values available to every template, e.g. `{{project}}`
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    variables: HashMap<String, String>,
+    #[serde(default, skip_serializing_if = "TagsConfig::is_empty")]
+    tags: TagsConfig,
+    /// globs of files or directories to skip when walking `<SOURCE>`, as with `--exclude`
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    #[validate(custom(function = "ignore_globs"))]
+    ignore: Vec<String>,
+    /// other destinations for some of the output files
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    dest: Vec<DestConfig>,
+    /// tags that doc blocks are required to have
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    rules: Vec<RuleConfig>,
+    #[serde(default, skip_serializing_if = "NewlineConfig::is_empty")]
+    newline: NewlineConfig,
+    #[serde(default, skip_serializing_if = "LatexConfig::is_empty")]
+    latex: LatexConfig,
+    #[serde(default, skip_serializing_if = "MarkdownConfig::is_empty")]
+    markdown: MarkdownConfig,
+    #[serde(default, skip_serializing_if = "UnicodeConfig::is_empty")]
+    unicode: UnicodeConfig,
+    #[serde(default, skip_serializing_if = "OutputConfig::is_empty")]
+    #[validate(custom(function = "output_globs"))]
+    output: OutputConfig,
+    /// equivalent to passing `--strict`
+    #[serde(default)]
+    strict: bool,
+    /// the size in bytes above which source files are skipped (see `--max-file-size`)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    max_file_size: Option<u64>,
+    /// the names whose `@if` sections are included (see `--define`)
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    define: Vec<String>,
+    /// the config files merged into this one, only read when a file is checked on its own
+    /// (see `check_config_file`), as `include_configs` takes them out of the merged config
+    #[serde(default, rename = "include", skip_serializing)]
+    _include: Vec<PathBuf>,
+}
+
+/// Settings that only apply to the files below `path`: its comment entries are tried before
+/// the top-level ones and its templates, if any, are used instead of the top-level templates
+#[derive(Serialize, Deserialize, Validate)]
+#[serde(deny_unknown_fields)]
+struct SourceConfig {
+    path: PathBuf,
+    #[serde(default)]
+    #[validate(
+        custom(function = "start_stop_match"),
+        custom(function = "comment_tag_groups"),
+        custom(function = "inline_groups")
+    )]
+    comment: Option<Vec<CommentConfig>>,
+    #[serde(default)]
+    template: Option<ConfigTemplates>,
+    /// `path` as an absolute path, once loaded
+    #[serde(skip)]
+    root: PathBuf,
+}
+
+/// The `tags` table: settings for the tags of every doc block
+#[derive(Serialize, Deserialize, Default)]
+#[serde(deny_unknown_fields)]
+struct TagsConfig {
+    /// values given to the tags that a doc block leaves out, e.g. `module = "uncategorized"`
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    defaults: BTreeMap<String, String>,
+    /// tags that are also shown in the body, as a bold label followed by their value, rather
+    /// than only being available to templates
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    keep: Vec<String>,
+    /// other names for tags, e.g. `filename = "file"` reads `@filename` as `@file`
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    aliases: BTreeMap<String, String>,
+    /// read tag names in lowercase, so that `@File`, `@FILE` and `@file` are the same tag
+    #[serde(default)]
+    case_insensitive: bool,
+    /// the values each tag is allowed to have, checked as the source files are read
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    validate: BTreeMap<String, TagCheck>,
+    /// where the value of a tag that continues onto the following lines ends
+    #[serde(default)]
+    continuation: TagContinuation,
+    /// the tag that leaves a doc block out of the docs, `nodoc` by default
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    nodoc: Option<String>,
+    /// the tags that can be repeated, each entry of which is kept for the templates; by
+    /// default `param` (named), `returns` and `throws`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    lists: Option<BTreeMap<String, TagList>>,
+    /// how the values of `@order` are compared
+    #[serde(default)]
+    order: OrderStyle,
+}
+
+/// How `@order` values are compared
+#[derive(Serialize, Deserialize, Default, Clone, Copy, PartialEq, Debug)]
+#[serde(rename_all = "lowercase")]
+enum OrderStyle {
+    /// as numbers, e.g. `-1`, `0.5` or `2`
+    #[default]
+    Number,
+    /// as dotted lists of whole numbers such as `2.1.3`, one number at a time, so that `2`
+    /// comes before `2.1` and `2.9` before `2.10`
+    Dotted,
+}
+
+/// How the entries of a repeated tag are read
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+enum TagList {
+    /// the first word of the value is a name, as in `@param x the x coordinate`
+    Named,
+    /// the value is only a description
+    Plain,
+}
+
+/// The lines after a tag that are read as part of its value
+#[derive(Serialize, Deserialize, Default, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+enum TagContinuation {
+    /// the lines up to the next tag or blank line
+    #[default]
+    Blank,
+    /// the lines up to the next tag; blank lines separate paragraphs of the value
+    Tag,
+    /// no lines: a tag's value is the rest of its line
+    None,
+}
+
+/// The allowed values of a tag: one of `enum`, and/or a match of `regex`
+#[derive(Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct TagCheck {
+    #[serde(default, rename = "enum", skip_serializing_if = "Option::is_none")]
+    values: Option<Vec<String>>,
+    #[serde(default, with = "serde_regex", skip_serializing_if = "Option::is_none")]
+    regex: Option<Regex>,
+}
+
+impl TagCheck {
+    /// Describes why `value` isn't allowed for `tag`, if it isn't
+    fn problem(&self, tag: &str, value: &str) -> Option<String> {
+        if let Some(values) = &self.values {
+            if !values.iter().any(|v| v == value) {
+                let options: Vec<_> = values.iter().map(|v| format!("`{}`", v)).collect();
+                return Some(format!(
+                    "Invalid value `{}` for `@{}`: expected one of {}.",
+                    value,
+                    tag,
+                    options.join(", ")
+                ));
+            }
+        }
+        if let Some(regex) = &self.regex {
+            if !regex.is_match(value) {
+                return Some(format!(
+                    "Invalid value `{}` for `@{}`: expected a match of `{}`.",
+                    value, tag, regex
+                ));
+            }
+        }
+        return None;
+    }
+}
+
+impl TagsConfig {
+    fn is_empty(&self) -> bool {
+        return self.defaults.is_empty()
+            && self.keep.is_empty()
+            && self.aliases.is_empty()
+            && !self.case_insensitive
+            && self.validate.is_empty()
+            && self.continuation == TagContinuation::default()
+            && self.nodoc.is_none()
+            && self.lists.is_none();
+    }
+
+    /// How the entries of `tag` are collected, if it can be repeated
+    fn list(&self, tag: &str) -> Option<TagList> {
+        if let Some(lists) = &self.lists {
+            return lists.get(tag).copied();
+        }
+        return match tag {
+            "param" => Some(TagList::Named),
+            "returns" | "throws" => Some(TagList::Plain),
+            _ => None,
+        };
+    }
+
+    fn nodoc(&self) -> &str {
+        return self.nodoc.as_deref().unwrap_or("nodoc");
+    }
+
+    /// The tag that `name` is read as: in lowercase with `case_insensitive`, and then as the
+    /// tag it is an alias of, if any
+    fn name(&self, name: &str) -> String {
+        if !self.case_insensitive {
+            return self.aliases.get(name).map_or(name, |t| t).to_string();
+        }
+        let name = name.to_lowercase();
+        return match self.aliases.iter().find(|(k, _)| k.to_lowercase() == name) {
+            Some((_, tag)) => tag.clone(),
+            None => name,
+        };
+    }
+
+    /// Fills in the default value of each tag missing from `doc`; blocks without any tags
+    /// are plain comments and are left alone
+    fn apply_defaults(&self, doc: &mut DocData) {
+        if doc.tags.is_empty() {
+            return;
+        }
+        for (tag, value) in &self.defaults {
+            if doc.tags.contains_key(tag) {
+                continue;
+            }
+            if tag == "order" {
+                doc.order = parse_order(value, self.order, Some((&doc.file, doc.line)));
+            }
+            doc.tags.insert(tag.clone(), value.clone());
+        }
+    }
+}
+
+/// The `newline` table: how line endings are read from source files and written to output
+/// files
+#[derive(Serialize, Deserialize, Default)]
+#[serde(deny_unknown_fields)]
+struct NewlineConfig {
+    /// read `\r\n` and `\r` line endings in source files as `\n`
+    #[serde(default)]
+    normalize: bool,
+    /// the line endings of the output files; if not given, they are left as rendered
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    output: Option<NewlineStyle>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy)]
+#[serde(rename_all = "lowercase")]
+enum NewlineStyle {
+    Lf,
+    Crlf,
+}
+
+impl NewlineConfig {
+    fn is_empty(&self) -> bool {
+        return !self.normalize && self.output.is_none();
+    }
+
+    /// Converts the line endings of the output `content` to the `output` style
+    fn apply(&self, content: String) -> String {
+        let Some(style) = self.output else {
+            return content;
+        };
+        let content = content.replace("\r\n", "\n").replace('\r', "\n");
+        return match style {
+            NewlineStyle::Lf => content,
+            NewlineStyle::Crlf => content.replace('\n', "\r\n"),
+        };
+    }
+}
+
+/// The `latex` table: settings for the built-in comment syntax of TeX and BibTeX files
+#[derive(Serialize, Deserialize, Default)]
+#[serde(deny_unknown_fields)]
+struct LatexConfig {
+    /// only read `%%` comment lines as docs, so that commented out code is left alone
+    #[serde(default)]
+    doc_marker: bool,
+}
+
+impl LatexConfig {
+    fn is_empty(&self) -> bool {
+        return !self.doc_marker;
+    }
+}
+
+/// The `markdown` table: how markdown files found in the source directories are read
+#[derive(Serialize, Deserialize, Default)]
+#[serde(deny_unknown_fields)]
+struct MarkdownConfig {
+    /// read each markdown file (that no `comment` entry matches) whole, as a single doc
+    /// block whose tags are the keys of its front matter
+    #[serde(default)]
+    passthrough: bool,
+}
+
+impl MarkdownConfig {
+    fn is_empty(&self) -> bool {
+        return !self.passthrough;
+    }
+}
+
+/// The `unicode` table: how the text of the source files is normalized
+#[derive(Serialize, Deserialize, Default)]
+#[serde(deny_unknown_fields)]
+struct UnicodeConfig {
+    /// convert the text to normalization form C, so that e.g. a letter followed by a
+    /// combining accent matches the same accented letter written as one character
+    #[serde(default)]
+    nfc: bool,
+}
+
+impl UnicodeConfig {
+    fn is_empty(&self) -> bool {
+        return !self.nfc;
+    }
+}
+
+/// Writes the output files matching `pattern` below `path` instead of `<DEST>`
+#[derive(Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct DestConfig {
+    #[serde(deserialize_with = "str_to_glob", serialize_with = "glob_to_str")]
+    pattern: Glob<'static>,
+    path: PathBuf,
+}
+
+/// Tags that doc blocks with the tag `when_tag` (or all tagged blocks) must have
+#[derive(Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RuleConfig {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    when_tag: Option<String>,
+    require: Vec<String>,
+    #[serde(default)]
+    level: RuleLevel,
+}
+
+/// Whether a broken rule stops the run or is reported as a warning
+#[derive(Serialize, Deserialize, Default, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+enum RuleLevel {
+    #[default]
+    Error,
+    Warning,
+}
+
+impl RuleConfig {
+    fn check(&self, doc: &DocData) -> Result<(), SrcDocError> {
+        let applies = match &self.when_tag {
+            Some(tag) => doc.tags.contains_key(tag),
+            None => !doc.tags.is_empty(),
+        };
+        let missing: Vec<_> = self
+            .require
+            .iter()
+            .filter(|t| !doc.tags.contains_key(*t))
+            .map(|t| format!("`@{}`", t))
+            .collect();
+        if !applies || missing.is_empty() {
+            return Ok(());
+        }
+        let msg = match &self.when_tag {
+            Some(tag) => format!(
+                "Doc block with `@{}` is missing {}",
+                tag,
+                missing.join(", ")
+            ),
+            None => format!("Doc block is missing {}", missing.join(", ")),
+        };
+        if self.level == RuleLevel::Warning {
+            warn!(file:% = doc.file.display(), line = doc.line; "{}", msg);
+            return Ok(());
+        }
+        return Err(SrcDocError::new(format!("{}.", msg))
+            .with_file(&doc.file)
+            .with_line(doc.line)
+            .with_kind(ErrorKind::Source));
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ConfigTemplates {
+    /// the language of the `foreach` and `all` templates; source sections that don't set it
+    /// use the top-level one
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    engine: Option<TemplateEngine>,
+    #[serde(default)]
+    foreach: Option<Vec<DocEachTemplate>>,
+    #[serde(default)]
+    all: Option<Vec<DocAllTemplate>>,
+    /// templates that the others can include with `{{> name}}`
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    partials: BTreeMap<String, PartialTemplate>,
+    /// the text of `partials` and of those inherited from the top-level table, once loaded
+    #[serde(skip)]
+    available_partials: BTreeMap<String, String>,
+}
+
+/// The template language of `foreach` and `all` templates
+#[derive(Serialize, Deserialize, Default, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+enum TemplateEngine {
+    #[default]
+    Mustache,
+    /// Handlebars, whose block helpers (`#if`, `#each`, `#with` and so on) can test for
+    /// optional tags
+    Handlebars,
+    /// Tera, with its filters and expressions; the partials are Tera templates that can be
+    /// extended, included or imported (for their macros) by name
+    Tera,
+}
+
+/// Renders the templates of a `template` table, each with its own `engine` or the table's
+#[derive(Default)]
+struct TemplateRenderer {
+    engine: TemplateEngine,
+    /// the partials available to the templates, by name
+    partials: BTreeMap<String, String>,
+    /// the partials, parsed as Tera templates the first time a Tera template is rendered
+    tera: Option<tera::Tera>,
+}
+
+impl TemplateRenderer {
+    fn new(templates: &ConfigTemplates) -> TemplateRenderer {
+        return TemplateRenderer {
+            engine: templates.engine(),
+            partials: templates.available_partials.clone(),
+            tera: None,
+        };
+    }
+
+    fn render(
+        &mut self,
+        engine: Option<TemplateEngine>,
+        template: &str,
+        data: &mustache::Data,
+    ) -> Result<String, TemplateError> {
+        return match engine.unwrap_or(self.engine) {
+            TemplateEngine::Mustache => {
+                Ok(mustache::compile_str(template)?.render_data_to_string(data)?)
+            }
+            TemplateEngine::Handlebars => {
+                let registry = handlebars::Handlebars::new();
+                Ok(registry.render_template(template, &data_to_json(data))?)
+            }
+            TemplateEngine::Tera => {
+                if self.tera.is_none() {
+                    let mut tera = tera::Tera::default();
+                    tera.add_raw_templates(self.partials.iter())?;
+                    self.tera = Some(tera);
+                }
+                let context = tera::Context::from_value(data_to_json(data))?;
+                // set just above
+                Ok(self.tera.as_mut().unwrap().render_str(template, &context)?)
+            }
+        };
+    }
+
+    /// Describes why `template` can't be compiled, if it can't
+    fn problem(&self, engine: Option<TemplateEngine>, template: &str) -> Option<String> {
+        return match engine.unwrap_or(self.engine) {
+            TemplateEngine::Mustache => {
+                mustache::compile_str(template).err().map(|e| e.to_string())
+            }
+            TemplateEngine::Handlebars => {
+                (handlebars::Template::compile(template).err()).map(|e| e.to_string())
+            }
+            TemplateEngine::Tera => {
+                let mut tera = tera::Tera::default();
+                (tera.add_raw_templates(self.partials.iter()))
+                    .and_then(|_| tera.add_raw_template("template", template))
+                    .err()
+                    .map(|e| tera_message(&e))
+            }
+        };
+    }
+}
+
+/// A Tera error along with its causes, which hold the details of what went wrong
+fn tera_message(error: &tera::Error) -> String {
+    let mut message = error.to_string();
+    let mut source = std::error::Error::source(error);
+    while let Some(e) = source {
+        message.push_str(&format!(": {}", e));
+        source = e.source();
+    }
+    return message;
+}
+
+/// The template data built for mustache, as the JSON that Handlebars and Tera render
+fn data_to_json(data: &mustache::Data) -> serde_json::Value {
+    return match data {
+        mustache::Data::String(x) => serde_json::Value::String(x.clone()),
+        mustache::Data::Bool(x) => serde_json::Value::Bool(*x),
+        mustache::Data::Vec(xs) => serde_json::Value::Array(xs.iter().map(data_to_json).collect()),
+        mustache::Data::Map(map) => serde_json::Value::Object(
+            map.iter()
+                .map(|(k, v)| (k.clone(), data_to_json(v)))
+                .collect(),
+        ),
+        mustache::Data::Null | mustache::Data::Fun(_) => serde_json::Value::Null,
+    };
+}
+
+/// A partial, given as a template string or as a file holding one
+#[derive(Serialize, Deserialize)]
+#[serde(untagged)]
+enum PartialTemplate {
+    Text(String),
+    File { template_file: PathBuf },
+}
+
+lazy_static! {
+    static ref PARTIAL_REGEX: Regex = Regex::new(r"\{\{>\s*(\S+?)\s*\}\}").unwrap();
+}
+
+/// Replaces each `{{> name}}` in `template` with the partial `name`; other partials are
+/// left for mustache to look up as files
+fn expand_partials(
+    template: &str,
+    partials: &BTreeMap<String, String>,
+    depth: usize,
+) -> Result<String, SrcDocError> {
+    if depth > 10 {
+        let msg = String::from("Config Error: partials include each other too deeply");
+        return Err(SrcDocError::new(msg).with_kind(ErrorKind::Config));
+    }
+    let mut result = String::new();
+    let mut last = 0;
+    for m in PARTIAL_REGEX.captures_iter(template) {
+        let (Some(whole), Some(partial)) = (m.get(0), partials.get(&m[1])) else {
+            continue;
+        };
+        result.push_str(&template[last..whole.start()]);
+        result.push_str(&expand_partials(partial, partials, depth + 1)?);
+        last = whole.end();
+    }
+    result.push_str(&template[last..]);
+    return Ok(result);
+}
+
+/// The fields naming a file that holds a template, which is read into the field without the
+/// `_file` suffix
+const TEMPLATE_FILE_FIELDS: [&str; 5] = [
+    "file_file",
+    "output_file",
+    "header_file",
+    "footer_file",
+    "template_file",
+];
+
+/// Reads the template `name` from the file `file` (relative to `dir`), if given
+fn load_template_file(
+    name: &str,
+    template: &mut String,
+    file: &mut Option<PathBuf>,
+    dir: &Path,
+) -> Result<(), SrcDocError> {
+    let Some(file) = file.take() else {
+        return Ok(());
+    };
+    let path = dir.join(file);
+    if !template.is_empty() {
+        let msg = format!(
+            "Config Error: `{}` and `{}_file` can't both be given",
+            name, name
+        );
+        return Err(SrcDocError::new(msg)
+            .with_file(&path)
+            .with_kind(ErrorKind::Config));
+    }
+    *template = fs::read_to_string(&path).map_err(|e| SrcDocError::from(e).with_file(&path))?;
+    return Ok(());
+}
+
+fn require_template(name: &str, template: &str) -> Result<(), SrcDocError> {
+    if template.is_empty() {
+        let msg = format!("Config Error: templates need `{}` or `{}_file`", name, name);
+        return Err(SrcDocError::new(msg).with_kind(ErrorKind::Config));
+    }
+    return Ok(());
+}
+
+/// Makes the template files named in an included config file relative to the directory of
+/// the file that includes it, `dir`
+fn rebase_template_files(config: &mut toml::Value, dir: &Path) {
+    match config {
+        toml::Value::Table(table) => {
+            for (key, value) in table.iter_mut() {
+                match value {
+                    toml::Value::String(file) if TEMPLATE_FILE_FIELDS.contains(&key.as_str()) => {
+                        let path = dir.join(&*file);
+                        let path = std::path::absolute(&path).unwrap_or(path);
+                        *file = path.to_string_lossy().into_owned();
+                    }
+                    _ => rebase_template_files(value, dir),
+                }
+            }
+        }
+        toml::Value::Array(array) => {
+            for value in array {
+                rebase_template_files(value, dir);
+            }
+        }
+        _ => (),
+    }
+}
+
+/// Resolves the paths of the `dest` and `source` entries of an included (or extended) config
+/// file against its directory, `dir`
+fn rebase_section_paths(config: &mut toml::Value, dir: &Path) {
+    for section in ["dest", "source"] {
+        let entries = config.get_mut(section).and_then(|d| d.as_array_mut());
+        for entry in entries.into_iter().flatten() {
+            if let Some(toml::Value::String(file)) = entry.get_mut("path") {
+                let path = dir.join(&*file);
+                let path = std::path::absolute(&path).unwrap_or(path);
+                *file = path.to_string_lossy().into_owned();
+            }
+        }
+    }
+}
+
+impl ConfigTemplates {
+    fn engine(&self) -> TemplateEngine {
+        return self.engine.unwrap_or_default();
+    }
+
+    /// Reads the templates given as files and expands the partials they include, using
+    /// `inherited` for partials not defined here; returns all the partials available
+    fn load_files(
+        &mut self,
+        dir: &Path,
+        inherited: &BTreeMap<String, String>,
+    ) -> Result<BTreeMap<String, String>, SrcDocError> {
+        let mut partials = inherited.clone();
+        for (name, partial) in self.partials.iter_mut() {
+            if let PartialTemplate::File { template_file } = partial {
+                let path = dir.join(&*template_file);
+                let text =
+                    fs::read_to_string(&path).map_err(|e| SrcDocError::from(e).with_file(&path))?;
+                *partial = PartialTemplate::Text(text);
+            }
+            if let PartialTemplate::Text(text) = partial {
+                partials.insert(name.clone(), text.clone());
+            }
+        }
+        for t in self.foreach.iter_mut().flatten() {
+            load_template_file("file", &mut t.file, &mut t.file_file, dir)?;
+            load_template_file("output", &mut t.output, &mut t.output_file, dir)?;
+            require_template("file", &t.file)?;
+            require_template("output", &t.output)?;
+            t.file = expand_partials(&t.file, &partials, 0)?;
+            t.output = expand_partials(&t.output, &partials, 0)?;
+            if let Right(order) = &t.order {
+                t.order = Right(expand_partials(order, &partials, 0)?);
+            }
+        }
+        for t in self.all.iter_mut().flatten() {
+            load_template_file("file", &mut t.file, &mut t.file_file, dir)?;
+            load_template_file("output", &mut t.output, &mut t.output_file, dir)?;
+            require_template("file", &t.file)?;
+            require_template("output", &t.output)?;
+            t.output = expand_partials(&t.output, &partials, 0)?;
+        }
+        self.available_partials = partials.clone();
+        return Ok(partials);
+    }
+}
+
+impl SrcDocConfig {
+    /// Reads the templates given as files, relative to the config file at `path` (or the
+    /// current directory), and expands the partials of all templates
+    fn load_template_files(&mut self, path: Option<&Path>) -> Result<(), SrcDocError> {
+        let dir = path.and_then(|p| p.parent()).unwrap_or(Path::new(""));
+        let mut partials = BTreeMap::new();
+        let mut engine = None;
+        if let Some(templates) = &mut self.template {
+            partials = templates.load_files(dir, &partials)?;
+            engine = templates.engine;
+        }
+        // source sections can also use the top-level partials and engine
+        for source in &mut self.source {
+            if let Some(templates) = &mut source.template {
+                templates.load_files(dir, &partials)?;
+                templates.engine = templates.engine.or(engine);
+            }
+        }
+        for w in &mut self.output.wrap {
+            load_template_file("header", &mut w.header, &mut w.header_file, dir)?;
+            load_template_file("footer", &mut w.footer, &mut w.footer_file, dir)?;
+            w.header = expand_partials(&w.header, &partials, 0)?;
+            w.footer = expand_partials(&w.footer, &partials, 0)?;
+        }
+        return Ok(());
+    }
+
+    fn new() -> SrcDocConfig {
+        return SrcDocConfig {
+            header: ConfigHeader {
+                version: Version::parse("0.2.1").unwrap(),
+                use_defaults: true,
+                extends: None,
+            },
+            template: None,
+            comment: Some(DEFAULT_COMMENT_MAP.clone()),
+            source: Vec::new(),
+            tag_pattern: None,
+            tag_prefix: None,
+            shebang: BTreeMap::new(),
+            variables: HashMap::new(),
+            tags: TagsConfig::default(),
+            rules: Vec::new(),
+            ignore: Vec::new(),
+            dest: Vec::new(),
+            newline: NewlineConfig::default(),
+            latex: LatexConfig::default(),
+            markdown: MarkdownConfig::default(),
+            unicode: UnicodeConfig::default(),
+            output: OutputConfig::default(),
+            strict: false,
+            max_file_size: None,
+            define: Vec::new(),
+            _include: Vec::new(),
+        };
+    }
+
+    /// Reads the config file at `path` (if any) with `overrides` (see `--set`) applied
+    fn from(path: Option<&Path>, overrides: &[String]) -> Result<SrcDocConfig, SrcDocError> {
+        let str = match path {
+            Some(p) => fs::read_to_string(p)?,
+            None => String::from("[header]\nversion = \"0.2.1\"\n"),
+        };
+        let mut result = SrcDocConfig::parse(&str, path, overrides)?;
+        result.load_template_files(path)?;
+        result.validate()?;
+        // `dest` and `source` paths are relative to the config file
+        let dir = path.and_then(|p| p.parent()).unwrap_or(Path::new(""));
+        for dest in &mut result.dest {
+            dest.path = dir.join(&dest.path);
+        }
+        for source in &mut result.source {
+            source.path = dir.join(&source.path);
+            source.root = resolve_path(&source.path);
+        }
+        if let (None, Some(prefix)) = (&result.tag_pattern, &result.tag_prefix) {
+            let pattern = format!(r"^\s*{}(?<tag>\S+)\s+(?<value>.*)", regex::escape(prefix));
+            result.tag_pattern = Some(Regex::new(&pattern).unwrap());
+        }
+        let mut comment_map = result.comment.unwrap_or_default();
+        if result.header.use_defaults {
+            comment_map.extend(DEFAULT_COMMENT_MAP.iter().cloned());
+            if result.latex.doc_marker {
+                let latex = latex_comment(false).extension.to_string();
+                for c in comment_map.iter_mut() {
+                    if c.builtin && c.extension.to_string() == latex {
+                        *c = latex_comment(true);
+                    }
+                }
+            }
+        }
+        result.comment = Some(comment_map);
+        return Ok(result);
+    }
+
+    /// Parses the config file content `str` (read from `path`), merging in any files it
+    /// `include`s and applying `overrides`, without validating it
+    fn parse(
+        str: &str,
+        path: Option<&Path>,
+        overrides: &[String],
+    ) -> Result<SrcDocConfig, SrcDocError> {
+        let format = path.map(ConfigFormat::of).unwrap_or(ConfigFormat::Toml);
+        let mut value = format.parse(str)?;
+        let plain = value.get("include").is_none() && extends(&value).is_none();
+        if format == ConfigFormat::Toml && overrides.is_empty() && plain {
+            // parse the text directly, so that errors point to their location in the file
+            return Ok(toml::from_str::<SrcDocConfig>(str)?);
+        }
+        if let Some(p) = path {
+            check_config_file(str, p)?;
+            include_configs(&mut value, p, &mut Vec::new())?;
+            extend_config(
+                &mut value,
+                p,
+                &mut fs::canonicalize(p).into_iter().collect(),
+            )?;
+        }
+        apply_overrides(&mut value, overrides)?;
+        return Ok(value.try_into::<SrcDocConfig>()?);
+    }
+
+    /// The comment syntaxes used for `file`: the entry that takes precedence, along with any
+    /// other entries tied with it that share its `extension` and `path`, so that a language
+    /// can have several comment patterns (e.g. `///` and `//!` in Rust)
+    fn find_comment_configs(&self, file: &Path) -> Vec<&CommentConfig> {
+        let source = self.find_source_config(file).map(|i| &self.source[i]);
+        let source_comments = source
+            .and_then(|s| s.comment.as_ref())
+            .into_iter()
+            .flatten();
+        let matching: Vec<_> = source_comments
+            .chain(self.comment.iter().flatten())
+            .filter(|c| c.matches(file))
+            .collect();
+        // user entries win over built-in ones, whatever order they were merged in; among
+        // those, the lowest `order` wins and ties go to the entry defined first
+        let best = matching.iter().min_by(|a, b| {
+            let order = a.order.partial_cmp(&b.order).unwrap_or(Ordering::Equal);
+            return a.builtin.cmp(&b.builtin).then(order);
+        });
+        let Some(best) = best.copied() else {
+            return Vec::new();
+        };
+        let same_path = |c: &CommentConfig| {
+            return c.path.as_ref().map(|p| p.to_string())
+                == best.path.as_ref().map(|p| p.to_string());
+        };
+        return matching
+            .into_iter()
+            .filter(|c| {
+                return c.builtin == best.builtin
+                    && c.order == best.order
+                    && c.extension.to_string() == best.extension.to_string()
+                    && same_path(c);
+            })
+            .collect();
+    }
+
+    /// The comment syntax for a file that matches no `comment` entry, found from the
+    /// interpreter named on its `#!` `first_line`
+    fn find_shebang_configs(&self, file: &Path, first_line: &str) -> Vec<&CommentConfig> {
+        return self
+            .find_shebang_ext(first_line)
+            .map(|ext| self.find_comment_configs(&file.with_extension(ext)))
+            .unwrap_or_default();
+    }
+
+    fn find_shebang_ext(&self, first_line: &str) -> Option<&str> {
+        let mut words = first_line.strip_prefix("#!")?.split_whitespace();
+        let mut interpreter = Path::new(words.next()?).file_name()?.to_str()?;
+        if interpreter == "env" {
+            // skip the options and variable assignments of `env`
+            interpreter = words.find(|w| !w.starts_with('-') && !w.contains('='))?;
+        }
+        // `python3.11` is looked up as given and then as `python`
+        let unversioned = interpreter.trim_end_matches(|c: char| c.is_ascii_digit() || c == '.');
+        let ext = [interpreter, unversioned].iter().find_map(|name| {
+            return self.shebang.get(*name).map(String::as_str).or_else(|| {
+                DEFAULT_SHEBANG_MAP
+                    .iter()
+                    .find(|(n, _)| n == name)
+                    .map(|x| x.1)
+            });
+        })?;
+        debug!(target: "files", "Using the comment syntax of `.{}` for `#!{}`", ext, interpreter);
+        return Some(ext);
+    }
+
+    /// The index of the most specific `[[source]]` section containing `file`, if any
+    fn find_source_config(&self, file: &Path) -> Option<usize> {
+        let file = resolve_path(file);
+        return (0..self.source.len())
+            .filter(|i| file.starts_with(&self.source[*i].root))
+            .max_by_key(|i| self.source[*i].root.components().count());
+    }
+
+    /// The templates used for `doc`: those of its `[[source]]` section (given by index), if
+    /// it has any, or else the top-level templates
+    fn templates_for(&self, doc: &DocData) -> (Option<usize>, Option<&ConfigTemplates>) {
+        return match doc.section.filter(|i| self.source[*i].template.is_some()) {
+            Some(i) => (Some(i), self.source[i].template.as_ref()),
+            None => (None, self.template.as_ref()),
+        };
+    }
+}
+
+/// The canonical form of `path`, or its absolute form for paths that don't exist (such as
+/// `<stdin>.rs`), so that `./src/a.rs`, `src/../src/a.rs` and `/repo/src/a.rs` are all found
+/// within `src`
+fn resolve_path(path: &Path) -> PathBuf {
+    return fs::canonicalize(path)
+        .or_else(|_| std::path::absolute(path))
+        .unwrap_or(path.to_path_buf());
+}
+
+/// Drops any `.` components, so that `./src/a.rs` is found within `src`
+fn without_cur_dir(path: &Path) -> PathBuf {
+    return path
+        .components()
+        .filter(|c| *c != std::path::Component::CurDir)
+        .collect();
+}
+
+fn valid_version(v: &Version) -> Result<(), ValidationError> {
+    // we're on version 0.2.1: any files semver compatible with 0.2 are fine
+    if VersionReq::parse("0.2").unwrap().matches(v) {
+        return Ok(());
+    } else {
+        return Err(ValidationError::new(
+            "File version incompatible with semver 0.2; run `simple-src-docs migrate-config` to upgrade older files",
+        ));
+    }
+}
+
+#[derive(Serialize, Deserialize, Validate)]
+#[serde(deny_unknown_fields)]
+struct ConfigHeader {
+    #[validate(custom(function = "valid_version"))]
+    version: Version,
+    /// if false, the built-in comment syntaxes (`DEFAULT_COMMENT_MAP`) are not used
+    #[serde(default = "yes")]
+    use_defaults: bool,
+    /// a config file, relative to this one, that this config is merged over
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    extends: Option<PathBuf>,
+}
+
+fn zero() -> f64 {
+    return 0.0;
+}
+fn one_line() -> usize {
+    return 1;
+}
+
+fn yes() -> bool {
+    return true;
+}
+
+fn left_zero() -> Either<f64, String> {
+    return Left(0.0);
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct DocEachTemplate {
+    tags: Vec<String>,
+    /// the engine of this template, if not the one of its `template` table
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    engine: Option<TemplateEngine>,
+    #[serde(default)]
+    file: String,
+    /// a file holding the `file` template, relative to the config file
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    file_file: Option<PathBuf>,
+    #[serde(with = "either::serde_untagged", default = "left_zero")]
+    order: Either<f64, String>,
+    #[serde(default)]
+    output: String,
+    /// a file holding the `output` template, relative to the config file
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    output_file: Option<PathBuf>,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct DocAllTemplate {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    engine: Option<TemplateEngine>,
+    #[serde(default)]
+    file: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    file_file: Option<PathBuf>,
+    tags: Vec<String>,
+    #[serde(default = "zero")]
+    order: f64,
+    #[serde(default)]
+    output: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    output_file: Option<PathBuf>,
+}
+
+enum TemplateError {
+    Mustache(mustache::Error),
+    Handlebars(handlebars::RenderError),
+    Tera(tera::Error),
+    Parse(ParseFloatError),
+}
+
+impl From<TemplateError> for SrcDocError {
+    fn from(value: TemplateError) -> Self {
+        return match value {
+            TemplateError::Parse(e) => SrcDocError::new(format!("Error parsing @order {}", e)),
+            TemplateError::Mustache(e) => SrcDocError::new(format!("Template parsing error {}", e)),
+            TemplateError::Handlebars(e) => SrcDocError::new(format!("Template error {}", e)),
+            TemplateError::Tera(e) => {
+                SrcDocError::new(format!("Template error {}", tera_message(&e)))
+            }
+        }
+        .with_kind(ErrorKind::Template);
+    }
+}
+
+impl From<mustache::Error> for TemplateError {
+    fn from(value: mustache::Error) -> Self {
+        return TemplateError::Mustache(value);
+    }
+}
+
+impl From<handlebars::RenderError> for TemplateError {
+    fn from(value: handlebars::RenderError) -> Self {
+        return TemplateError::Handlebars(value);
+    }
+}
+
+impl From<tera::Error> for TemplateError {
+    fn from(value: tera::Error) -> Self {
+        return TemplateError::Tera(value);
+    }
+}
+
+impl From<ParseFloatError> for TemplateError {
+    fn from(value: ParseFloatError) -> Self {
+        return TemplateError::Parse(value);
+    }
+}
+
+/// The position of a doc block in its output file, read from its `@order` (see `OrderStyle`)
+#[derive(Debug, Clone, Serialize, Deserialize)]
+enum OrderKey {
+    Number(f64),
+    Dotted(Vec<i64>),
+}
+
+impl Default for OrderKey {
+    fn default() -> Self {
+        return OrderKey::Number(0.0);
+    }
+}
+
+impl OrderKey {
+    fn parse(order_str: &str, style: OrderStyle) -> Result<OrderKey, String> {
+        let order_str = order_str.trim();
+        if style == OrderStyle::Number {
+            return match order_str.parse() {
+                Ok(x) => Ok(OrderKey::Number(x)),
+                Err(e) => Err(e.to_string()),
+            };
+        }
+        // the sign of a negative number with several parts would only apply to the first
+        if order_str.starts_with('-') && order_str.contains('.') {
+            return Err(String::from("only whole numbers can be negative"));
+        }
+        let mut components = Vec::new();
+        for component in order_str.split('.') {
+            match component.trim().parse() {
+                Ok(x) => components.push(x),
+                Err(e) => return Err(format!("`{}` is not a whole number ({})", component, e)),
+            }
+        }
+        return Ok(OrderKey::Dotted(components));
+    }
+
+    /// The order given as a number in the config file, such as the `order` of a template
+    fn number(order: f64, style: OrderStyle) -> OrderKey {
+        return match style {
+            OrderStyle::Number => OrderKey::Number(order),
+            OrderStyle::Dotted => parse_order(&order.to_string(), style, None),
+        };
+    }
+
+    /// The first number of the order and the count of numbers, used to compare the two
+    /// styles (e.g. the default order with a dotted one)
+    fn first(&self) -> (f64, usize) {
+        return match self {
+            OrderKey::Number(x) => (*x, 1),
+            OrderKey::Dotted(components) => (
+                components.first().map_or(0.0, |x| *x as f64),
+                components.len(),
+            ),
+        };
+    }
+}
+
+impl Ord for OrderKey {
+    fn cmp(&self, other: &Self) -> Ordering {
+        return match (self, other) {
+            (OrderKey::Dotted(a), OrderKey::Dotted(b)) => a.cmp(b),
+            _ => {
+                let ((a, a_len), (b, b_len)) = (self.first(), other.first());
+                a.total_cmp(&b).then(a_len.cmp(&b_len))
+            }
+        };
+    }
+}
+
+impl PartialOrd for OrderKey {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        return Some(self.cmp(other));
+    }
+}
+
+impl PartialEq for OrderKey {
+    fn eq(&self, other: &Self) -> bool {
+        return self.cmp(other) == Ordering::Equal;
+    }
+}
+
+impl Eq for OrderKey {}
+
+/// Parses an `@order` value, warning about (and ignoring) invalid values; `location` is
+/// the source file and line the value was read from, if known
+fn parse_order(order_str: &str, style: OrderStyle, location: Option<(&Path, usize)>) -> OrderKey {
+    return match OrderKey::parse(order_str, style) {
+        Ok(x) => x,
+        Err(e) => {
+            let msg = format!("Error while evaluating @order {order_str}: {e}");
+            match location {
+                Some((file, line)) => warn!(file:% = file.display(), line = line; "{}", msg),
+                None => warn!("{}", msg),
+            }
+            OrderKey::default()
+        }
+    };
+}
+
+/// True if `doc` has all of the given `tags`
+fn matches_tags(tags: &[String], doc: &DocData) -> bool {
+    return tags.iter().all(|tag| doc.tags.contains_key(tag));
+}
+
+impl DocEachTemplate {
+    fn apply(
+        &self,
+        docs: &Vec<&DocData>,
+        variables: &HashMap<String, String>,
+        style: OrderStyle,
+        renderer: &mut TemplateRenderer,
+        result: &mut HashMap<String, Vec<(OrderKey, String)>>,
+    ) -> Result<(), TemplateError> {
+        for doc in docs {
+            if !matches_tags(&self.tags, doc) {
+                continue;
+            }
+
+            // tags take precedence over variables of the same name
+            let mut builder = MapBuilder::new();
+            for (key, val) in variables {
+                builder = builder.insert_str(key, val);
+            }
+            for (key, val) in &doc.tags {
+                builder = builder.insert_str(key, val);
+            }
+            builder = builder.insert_str("__body__", &doc.body);
+            if let Some(signature) = &doc.signature {
+                builder = builder.insert_str("__signature__", signature);
+            }
+            builder = builder.insert_str("__source_file__", doc.source_path());
+            builder = builder.insert_str("__line__", doc.line.to_string());
+            builder = doc.insert_lists(builder);
+            let data = builder.build();
+
+            let file = renderer.render(self.engine, &self.file, &data)?;
+            let order = match &self.order {
+                Left(n) => OrderKey::number(*n, style),
+                Right(str) => {
+                    let order_str = renderer.render(self.engine, str, &data)?;
+                    parse_order(&order_str, style, Some((&doc.file, doc.line)))
+                }
+            };
+            let body = renderer.render(self.engine, &self.output, &data)?;
+            trace!(target: "templates", "foreach template {:?} wrote to {}", self.tags, file);
+            let items = result.entry(file).or_default();
+            items.push((order, body));
+        }
+        return Ok(());
+    }
+}
+
+impl DocAllTemplate {
+    fn apply(
+        &self,
+        docs: &Vec<&DocData>,
+        variables: &HashMap<String, String>,
+        style: OrderStyle,
+        renderer: &mut TemplateRenderer,
+        result: &mut HashMap<String, Vec<(OrderKey, String)>>,
+    ) -> Result<(), TemplateError> {
+        let mut builder = MapBuilder::new();
+        for (key, val) in variables {
+            builder = builder.insert_str(key, val);
+        }
+        builder = builder.insert_vec("items", |mut builder| {
+            for s in docs {
+                if !matches_tags(&self.tags, s) {
+                    continue;
+                }
+                builder = builder.push_map(|mut map_builder| {
+                    for (k, v) in &s.tags {
+                        map_builder = map_builder.insert_str(k, v);
+                    }
+                    map_builder = map_builder.insert_str("__body__", &s.body);
+                    if let Some(signature) = &s.signature {
+                        map_builder = map_builder.insert_str("__signature__", signature);
+                    }
+                    map_builder = map_builder.insert_str("__source_file__", s.source_path());
+                    map_builder = map_builder.insert_str("__line__", s.line.to_string());
+                    return s.insert_lists(map_builder);
+                });
+            }
+            return builder;
+        });
+
+        let data = builder.build();
+        let body = renderer.render(self.engine, &self.output, &data)?;
+        trace!(target: "templates", "all template {:?} wrote to {}", self.tags, self.file);
+        let items = result.entry(self.file.clone()).or_default();
+        items.push((OrderKey::number(self.order, style), body));
+        return Ok(());
+    }
+}
+
+impl SrcDocConfig {
+    fn apply(
+        &self,
+        data: &Vec<&DocData>,
+    ) -> Result<HashMap<String, Vec<(OrderKey, String)>>, TemplateError> {
+        let mut results = HashMap::new();
+        // docs from `[[source]]` sections with their own templates are rendered separately
+        let mut groups: Vec<(Option<usize>, Option<&ConfigTemplates>, Vec<&DocData>)> = Vec::new();
+        for doc in data {
+            let (source, templates) = self.templates_for(doc);
+            match groups.iter_mut().find(|(s, _, _)| *s == source) {
+                Some((_, _, docs)) => docs.push(doc),
+                None => groups.push((source, templates, vec![doc])),
+            }
+        }
+        for (_, templates, docs) in &groups {
+            let Some(templates) = templates else {
+                continue;
+            };
+            let mut renderer = TemplateRenderer::new(templates);
+            if let Some(each_templates) = &templates.foreach {
+                for each_template in each_templates {
+                    each_template.apply(
+                        docs,
+                        &self.variables,
+                        self.tags.order,
+                        &mut renderer,
+                        &mut results,
+                    )?;
+                }
+            }
+
+            if let Some(all_templates) = &templates.all {
+                for all_template in all_templates {
+                    all_template.apply(
+                        docs,
+                        &self.variables,
+                        self.tags.order,
+                        &mut renderer,
+                        &mut results,
+                    )?;
+                }
+            }
+        }
+
+        for doc in data {
+            if doc.tags.contains_key("file") {
+                for file in doc.files() {
+                    let items = results.entry(String::from(file)).or_default();
+                    items.push((doc.order.clone(), doc.body.clone()));
+                }
+            } else if doc.tags.keys().any(|k| k != "order") && !self.uses(doc) {
+                warn!(
+                    file:% = doc.file.display(), line = doc.line;
+                    "Doc block has tags but no `@file` and matches no template"
+                );
+            }
+        }
+
+        return Ok(results);
+    }
+
+    /// The destination of the first `dest` entry whose pattern matches the output `file`
+    fn dest_for(&self, file: &Path) -> Option<&Path> {
+        return self
+            .dest
+            .iter()
+            .find(|d| {
+                return d.pattern.is_match(file)
+                    || file
+                        .file_name()
+                        .is_some_and(|name| d.pattern.is_match(name));
+            })
+            .map(|d| d.path.as_path());
+    }
+
+    /// True if any template applies to `doc`
+    fn uses(&self, doc: &DocData) -> bool {
+        let Some(templates) = self.templates_for(doc).1 else {
+            return false;
+        };
+        let each = templates
+            .foreach
+            .iter()
+            .flatten()
+            .any(|t| matches_tags(&t.tags, doc));
+        let all = templates
+            .all
+            .iter()
+            .flatten()
+            .any(|t| matches_tags(&t.tags, doc));
+        return each || all;
+    }
+}
+
+// Comments ////////////////////////////////////////////////////////////////////////////////
+
+struct Comments<'a, T: Iterator<Item = String>> {
+    lines: T,
+    /// the number of lines read so far
+    line: usize,
+    in_comment: bool,
+    /// the first and last lines of the current (or last) comment, delimiters included
+    opened: usize,
+    closed: usize,
+    /// the number of nested comments open within the current one (see `nested_start`)
+    depth: usize,
+    /// the end of a comment that closed on the same line as some of its content, returned
+    /// after that content
+    pending: Option<CommentResult>,
+    /// the marker of the fenced code block open within the current comment
+    fence: Option<String>,
+    /// the lines read since the last line of the current comment, which `merge` may allow
+    /// it to continue after
+    gap: Option<CommentGap>,
+    /// the line of the file that each of the next lines is on, when they aren't on
+    /// consecutive lines (as in a notebook)
+    numbers: VecDeque<usize>,
+    config: &'a CommentConfig,
+}
+
+#[derive(Clone, Copy)]
+struct CommentGap {
+    /// the last line of the comment before the gap
+    last: usize,
+    blank: bool,
+    /// the number of (non-blank) code lines in the gap
+    code: usize,
+}
+
+impl<'a, T: Iterator<Item = String>> Comments<'a, T> {
+    fn new(lines: T, config: &'a CommentConfig) -> Comments<'a, T> {
+        return Comments {
+            lines,
+            line: 0,
+            in_comment: false,
+            opened: 0,
+            closed: 0,
+            depth: 0,
+            pending: None,
+            fence: None,
+            gap: None,
+            numbers: VecDeque::new(),
+            config,
+        };
+    }
+}
+
+impl<'a, T: Iterator<Item = String>> Comments<'a, T> {
+    /// The position of the `stop` that closes the current comment within `text`, if any;
+    /// with `nested_start`, the openers and closers of nested comments are skipped over
+    fn find_stop(&mut self, text: &str) -> Option<usize> {
+        let end_p = self.config.stop.as_ref().unwrap();
+        let Some(nested_p) = &self.config.nested_start else {
+            return end_p.find(text).map(|m| m.start());
+        };
+        let mut pos = 0;
+        while pos <= text.len() {
+            let open = nested_p.find_at(text, pos);
+            let close = end_p.find_at(text, pos);
+            match (open, close) {
+                (Some(o), Some(c)) if o.start() < c.start() => {
+                    self.depth += 1;
+                    pos = o.end().max(pos + 1);
+                }
+                (_, Some(c)) if self.depth == 0 => return Some(c.start()),
+                (_, Some(c)) => {
+                    self.depth -= 1;
+                    pos = c.end().max(pos + 1);
+                }
+                (Some(o), None) => {
+                    self.depth += 1;
+                    pos = o.end().max(pos + 1);
+                }
+                (None, None) => return None,
+            }
+        }
+        return None;
+    }
+
+    /// The comment line for `text`, the part of a line kept by `each_line`; lines of a fenced
+    /// code block are marked as verbatim
+    fn content(&mut self, text: &str) -> CommentResult {
+        let trimmed = text.trim_start();
+        let verbatim = match &self.fence {
+            None => match fence_marker(trimmed) {
+                Some(marker) => {
+                    self.fence = Some(String::from(marker));
+                    true
+                }
+                None => false,
+            },
+            Some(marker) => {
+                let closes = fence_marker(trimmed).is_some_and(|m| {
+                    m.starts_with(marker.as_str()) && trimmed[m.len()..].trim().is_empty()
+                });
+                if closes {
+                    self.fence = None;
+                }
+                true
+            }
+        };
+        return CommentResult {
+            value: String::from(text),
+            line: self.line,
+            last: false,
+            verbatim,
+            column: None,
+        };
+    }
+}
+
+/// Removes the leading whitespace common to the non-blank `lines` of a block, so that
+/// indented comments don't turn into markdown code blocks. The text of a comment that starts
+/// on its opening line (`opener`, as in `"""Summary`) is left out.
+fn dedent(lines: &mut [CommentResult], opener: Option<usize>) {
+    let counted = |c: &CommentResult| Some(c.line) != opener;
+    // lines that `each_line` doesn't match (such as those missing the `*` of a C comment)
+    // lose their indentation up to where the text of the lines it does match starts
+    let texts = lines.iter().filter(|c| !c.value.trim().is_empty());
+    if let Some(margin) = texts.filter_map(|c| c.column).min() {
+        for comment in lines
+            .iter_mut()
+            .filter(|c| c.column.is_none() && counted(c))
+        {
+            let indent = comment.value.len() - comment.value.trim_start_matches([' ', '\t']).len();
+            comment.value.drain(..indent.min(margin));
+        }
+    }
+    let len = common_indent(
+        lines
+            .iter()
+            .filter(|c| counted(c))
+            .map(|c| c.value.as_str()),
+    );
+    if len == 0 {
+        return;
+    }
+    for comment in lines.iter_mut().filter(|c| counted(c)) {
+        if !comment.value.trim().is_empty() {
+            comment.value.drain(..len);
+        }
+    }
+}
+
+/// The length of the leading whitespace shared by the non-blank `lines`
+fn common_indent<'a>(lines: impl Iterator<Item = &'a str>) -> usize {
+    let mut common: Option<&str> = None;
+    for line in lines.filter(|l| !l.trim().is_empty()) {
+        let indent = &line[..line.len() - line.trim_start().len()];
+        common = Some(match common {
+            None => indent,
+            Some(prev) => {
+                let len = prev
+                    .char_indices()
+                    .zip(indent.chars())
+                    .find(|((_, a), b)| a != b)
+                    .map_or(prev.len().min(indent.len()), |((i, _), _)| i);
+                &prev[..len]
+            }
+        });
+    }
+    return common.map_or(0, |c| c.len());
+}
+
+/// The opening run of three or more backticks or tildes that marks a fenced code block
+fn fence_marker(line: &str) -> Option<&str> {
+    let c = line.chars().next().filter(|c| *c == '`' || *c == '~')?;
+    let len = line.len() - line.trim_start_matches(c).len();
+    return (len >= 3).then(|| &line[..len]);
+}
+
+#[derive(Debug)]
+struct CommentResult {
+    value: String,
+    /// the line of the source file this comment was found on
+    line: usize,
+    last: bool,
+    /// the line is part of a fenced code block, and must not be read as a tag
+    verbatim: bool,
+    /// where the text of the line starts in the source line, for lines of a multi-line
+    /// comment that `each_line` matches
+    column: Option<usize>,
+}
+
+impl<'a, T: Iterator<Item = String>> Iterator for Comments<'a, T> {
+    type Item = CommentResult;
+    fn next(&mut self) -> Option<Self::Item> {
+        // each line that doesn't produce a result moves on to the next one
+        loop {
+            if let Some(result) = self.pending.take() {
+                return Some(result);
+            }
+            let value = match self.lines.next() {
+                None if self.in_comment => {
+                    self.in_comment = false;
+                    self.closed = self.gap.take().map_or(self.line, |g| g.last);
+                    return Some(CommentResult {
+                        line: self.line,
+                        value: String::new(),
+                        last: true,
+                        verbatim: false,
+                        column: None,
+                    });
+                }
+                None => return None,
+                Some(x) => x,
+            };
+            let previous = self.line;
+            self.line = self.numbers.pop_front().unwrap_or(self.line + 1);
+
+            if self.config.start.is_none() {
+                // single line comment syntax
+                let maybe_cap = self
+                    .config
+                    .each_line
+                    .as_ref()
+                    .unwrap()
+                    .captures(value.as_str());
+                if let Some(capture) = maybe_cap {
+                    if !self.in_comment {
+                        self.opened = self.line;
+                        self.fence = None;
+                    }
+                    self.in_comment = true;
+                    let gap = self.gap.take();
+                    if let Some(cap_match) = first_group(&capture) {
+                        let result = self.content(cap_match.as_str());
+                        // blank lines between merged comments separate their paragraphs
+                        if !gap.is_some_and(|g| g.blank) {
+                            return Some(result);
+                        }
+                        self.pending = Some(result);
+                        return Some(CommentResult {
+                            line: self.line,
+                            value: String::new(),
+                            last: false,
+                            verbatim: false,
+                            column: None,
+                        });
+                    }
+                } else if self.in_comment {
+                    let blank = value.trim().is_empty();
+                    let mut gap = (self.gap).unwrap_or(CommentGap {
+                        last: previous,
+                        blank: false,
+                        code: 0,
+                    });
+                    gap.blank |= blank;
+                    gap.code += usize::from(!blank);
+                    let merged = match self.config.merge {
+                        Some(CommentMerge::Blank) => gap.code == 0,
+                        Some(CommentMerge::Line) => gap.code <= 1,
+                        None => false,
+                    };
+                    if merged {
+                        self.gap = Some(gap);
+                        continue;
+                    }
+                    self.gap = None;
+                    self.in_comment = false;
+                    self.closed = gap.last;
+                    return Some(CommentResult {
+                        line: self.line,
+                        value: String::new(),
+                        last: true,
+                        verbatim: false,
+                        column: None,
+                    });
+                }
+            } else {
+                // multiline comment syntax
+                // validated invariant: if `start` is set, then `stop` is set
+                let start_p = self.config.start.as_ref().unwrap();
+                if !self.in_comment {
+                    if let Some(capture) = start_p.captures(&value) {
+                        self.in_comment = true;
+                        self.opened = self.line;
+                        self.depth = 0;
+                        self.fence = None;
+                        // text captured by `start` (or else, the text after it) is the first line
+                        // of the comment, which can also close on that line (e.g. `"""text"""`)
+                        let opener = capture.get(0).map_or(value.len(), |m| m.end());
+                        let rest = first_group(&capture).map_or(&value[opener..], |m| m.as_str());
+                        let content = match self.find_stop(rest) {
+                            Some(end) => {
+                                self.in_comment = false;
+                                self.closed = self.line;
+                                self.pending = Some(CommentResult {
+                                    line: self.line,
+                                    value: String::new(),
+                                    last: true,
+                                    verbatim: false,
+                                    column: None,
+                                });
+                                &rest[..end]
+                            }
+                            None => rest,
+                        };
+                        if content.trim().is_empty() {
+                            continue;
+                        }
+                        return Some(CommentResult {
+                            line: self.line,
+                            value: String::from(content),
+                            last: false,
+                            verbatim: false,
+                            column: None,
+                        });
+                    }
+                }
+                let mut value = value;
+                if self.in_comment {
+                    if let Some(end) = self.find_stop(&value) {
+                        let result = CommentResult {
+                            line: self.line,
+                            value: String::new(),
+                            last: true,
+                            verbatim: false,
+                            column: None,
+                        };
+                        self.in_comment = false;
+                        self.closed = self.line;
+                        // any text before `stop` is the comment's last line
+                        if value[..end].trim().is_empty() {
+                            return Some(result);
+                        }
+                        self.pending = Some(result);
+                        value.truncate(end);
+                    }
+                }
+                if self.in_comment || self.pending.is_some() {
+                    // without `each_line`, indentation is left for `dedent` to remove
+                    let captured = (self.config.each_line.as_ref())
+                        .and_then(|r| r.captures(&value))
+                        .and_then(|c| first_group(&c))
+                        .map(|m| (m.start(), String::from(m.as_str())));
+                    let mut result = self.content(captured.as_ref().map_or(&value, |c| &c.1));
+                    result.column = captured.map(|c| c.0);
+                    return Some(result);
+                }
             }
+        }
+    }
+}
 
-            if let Some(m) = tag_r.captures(&comment.value) {
-                if &m["tag"] == "__body__" {
-                    eprintln!("The tag `__body__` is reserved.");
-                    std::process::exit(1);
-                } else if &m["tag"] == "order" {
-                    order = parse_order(&m["value"]);
-                } else {
-                    last_tag = Some(String::from(&m["tag"]));
+// Parsed Docs /////////////////////////////////////////////////////////////////////////////
+
+struct DocIterator<'a, T: Iterator<Item = String>> {
+    comments: Comments<'a, T>,
+    /// the source file the comments are read from, used when reporting problems
+    file: PathBuf,
+    /// matches a line that starts a tag, with the groups `tag` and `value`
+    /// the tag patterns, tried in order on each line
+    tag_rs: Vec<&'a Regex>,
+    tags: &'a TagsConfig,
+    /// what marks a tag, `@` unless `tag_prefix` is set; used for the bare `@end` and `@nodoc`
+    /// lines, which the tag patterns (expecting a value) don't match
+    prefix: &'a str,
+    /// the names whose `@if` sections are read (see `--define`)
+    define: &'a [String],
+    /// the doc blocks of the last comment read that are yet to be returned, with the lines
+    /// they span
+    queued: VecDeque<(Vec<CommentResult>, (usize, usize))>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct DocData {
+    tags: HashMap<String, String>,
+    order: OrderKey,
+    body: String,
+    /// the source file and line the block starts on, used when reporting problems
+    file: PathBuf,
+    line: usize,
+    /// the definition documented by the block (see `CommentConfig::signature`)
+    #[serde(default)]
+    signature: Option<String>,
+    /// the first and last lines of the comment the block was read from (or of its part,
+    /// for a comment split by `@end`)
+    #[serde(skip)]
+    span: (usize, usize),
+    /// `file`, relative to the `<SOURCE>` directory it was found in
+    #[serde(skip)]
+    source_file: PathBuf,
+    /// the `[[source]]` section (by index) that `file` is in, if any
+    #[serde(skip)]
+    section: Option<usize>,
+    /// the files spliced into the body by `@include`
+    #[serde(skip)]
+    includes: Vec<PathBuf>,
+    /// the entries of each tag that can be repeated (see `TagsConfig::lists`)
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    lists: BTreeMap<String, Vec<TagItem>>,
+    /// every value of each tag given more than once, in order; `tags` holds the last one
+    /// (or for `@file`, all of them)
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    repeated: BTreeMap<String, Vec<String>>,
+}
+
+/// An entry of a tag that can be repeated, such as `@param x the x coordinate`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct TagItem {
+    /// the type given in braces before the name, as in JSDoc's `@param {number} x`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    kind: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    name: Option<String>,
+    description: String,
+}
+
+impl TagItem {
+    /// Reads the value of a tag: an optional `{type}`, the name (if `named`) and then the
+    /// description
+    fn parse(value: &str, named: bool) -> TagItem {
+        let mut rest = value.trim();
+        let mut kind = None;
+        if let Some((inner, after)) = rest.strip_prefix('{').and_then(|r| r.split_once('}')) {
+            kind = Some(String::from(inner.trim()));
+            rest = after.trim_start();
+        }
+        let mut name = None;
+        if named {
+            let (first, description) = rest.split_once(char::is_whitespace).unwrap_or((rest, ""));
+            name = Some(String::from(first));
+            rest = description.trim_start();
+        }
+        return TagItem {
+            kind,
+            name,
+            description: String::from(rest),
+        };
+    }
+
+    fn insert(&self, mut builder: MapBuilder) -> MapBuilder {
+        if let Some(kind) = &self.kind {
+            builder = builder.insert_str("type", kind);
+        }
+        if let Some(name) = &self.name {
+            builder = builder.insert_str("name", name);
+        }
+        return builder.insert_str("description", &self.description);
+    }
+}
+
+/// The names of the fields that simple-src-docs adds to a template's data (`__body__` and so
+/// on), which `DocData::insert_lists` leaves to them
+const RESERVED_FIELDS: [&str; 4] = ["body", "signature", "source_file", "line"];
+
+lazy_static! {
+    /// a mustache tag such as `{{name}}`, along with the third brace of `{{{name}}}`
+    static ref MUSTACHE_TAG: Regex = Regex::new(r"(\{?)\{\{([^}]*)\}\}").unwrap();
+    /// the commas separating the files of `@file a.md, b.md`; other commas are part of a
+    /// file name, as in `@file a,b.md`
+    static ref FILE_SEPARATOR: Regex = Regex::new(r",(?:\s+|$)").unwrap();
+}
+
+/// Makes the `{{name}}` variables of `template` insert their values as written, like
+/// `{{{name}}}`, rather than escaped for HTML: tag values are text, and often file names
+fn unescaped(template: &str) -> String {
+    let replaced = MUSTACHE_TAG.replace_all(template, |c: &regex::Captures| {
+        let name = c[2].trim_start();
+        if !c[1].is_empty() || name.starts_with(['#', '^', '/', '!', '>', '&', '=', '{']) {
+            return String::from(&c[0]);
+        }
+        return format!("{{{{&{}}}}}", &c[2]);
+    });
+    return replaced.into_owned();
+}
+
+impl DocData {
+    /// Adds the entries of the repeated tags to a template's data, as `__param__` and so on,
+    /// and the values of the other tags, as `__see__` for `@see` and so on
+    fn insert_lists(&self, mut builder: MapBuilder) -> MapBuilder {
+        for (tag, items) in &self.lists {
+            builder = builder.insert_vec(format!("__{}__", tag), |mut list| {
+                for item in items {
+                    list = list.push_map(|map| item.insert(map));
+                }
+                return list;
+            });
+        }
+        for (tag, value) in &self.tags {
+            // leave the fields set by simple-src-docs itself alone
+            if self.lists.contains_key(tag) || RESERVED_FIELDS.contains(&tag.as_str()) {
+                continue;
+            }
+            let values = match self.repeated.get(tag) {
+                Some(values) => values.as_slice(),
+                None => std::slice::from_ref(value),
+            };
+            builder = builder.insert_vec(format!("__{}__", tag), |mut list| {
+                for value in values {
+                    list = list.push_str(value);
                 }
-                tags.insert(String::from(&m["tag"]), String::from(m["value"].trim()));
+                return list;
+            });
+        }
+        return builder;
+    }
 
-            } else {
-                if comment.value.trim().is_empty() {
+    /// `source_file` with `/` separators, as used in links
+    fn source_path(&self) -> String {
+        let file = match self.source_file.as_os_str().is_empty() {
+            true => without_cur_dir(&self.file),
+            false => self.source_file.clone(),
+        };
+        let parts: Vec<_> = file
+            .components()
+            .map(|c| c.as_os_str().to_string_lossy())
+            .collect();
+        return parts.join("/");
+    }
+
+    /// The files the block is written to: the list given by its `@file` tags, which may be
+    /// repeated, separated by commas followed by whitespace (see `FILE_SEPARATOR`)
+    fn files(&self) -> Vec<&str> {
+        let files = self.tags.get("file").map_or("", |f| f.as_str());
+        return FILE_SEPARATOR
+            .split(files)
+            .map(|f| f.trim())
+            .filter(|f| !f.is_empty())
+            .collect();
+    }
+
+    /// Renders the tag values that are mustache templates (e.g. `@file commands/{{name}}.md`)
+    /// with the other tags of the block, as written, and the config's `variables`
+    fn render_tags(
+        &mut self,
+        variables: &HashMap<String, String>,
+        style: OrderStyle,
+    ) -> Result<(), SrcDocError> {
+        let templated: Vec<_> = (self.tags.iter())
+            .filter(|(_, v)| v.contains("{{"))
+            .map(|(k, v)| (k.clone(), v.clone()))
+            .collect();
+        let repeated = self.repeated.values().flatten().any(|v| v.contains("{{"));
+        if templated.is_empty() && !repeated {
+            return Ok(());
+        }
+        let mut builder = MapBuilder::new();
+        for (key, val) in variables {
+            builder = builder.insert_str(key, val);
+        }
+        for (key, val) in &self.tags {
+            builder = builder.insert_str(key, val);
+        }
+        builder = builder.insert_str("__source_file__", self.source_path());
+        builder = builder.insert_str("__line__", self.line.to_string());
+        let data = builder.build();
+        let render = |tag: &str, value: &str| {
+            return mustache::compile_str(&unescaped(value))
+                .and_then(|t| t.render_data_to_string(&data))
+                .map_err(|e| {
+                    let e = SrcDocError::new(format!("Invalid template in `@{}`: {}", tag, e))
+                        .with_file(&self.file)
+                        .with_line(self.line);
+                    return e.with_kind(ErrorKind::Source);
+                });
+        };
+        let mut rendered_tags = Vec::new();
+        for (tag, value) in templated {
+            let rendered = render(&tag, &value)?;
+            rendered_tags.push((tag, rendered));
+        }
+        let mut rendered_repeated = self.repeated.clone();
+        for (tag, values) in &mut rendered_repeated {
+            for value in values.iter_mut().filter(|v| v.contains("{{")) {
+                *value = render(tag, value)?;
+            }
+        }
+        for (tag, rendered) in rendered_tags {
+            if tag == "order" {
+                self.order = parse_order(&rendered, style, Some((&self.file, self.line)));
+            }
+            self.tags.insert(tag, rendered);
+        }
+        self.repeated = rendered_repeated;
+        return Ok(());
+    }
+}
+
+impl<'a, T: Iterator<Item = String>> DocIterator<'a, T> {
+    fn new(
+        comments: Comments<'a, T>,
+        file: &Path,
+        tag_rs: Vec<&'a Regex>,
+        config: &'a SrcDocConfig,
+    ) -> DocIterator<'a, T> {
+        return DocIterator {
+            comments,
+            file: file.to_path_buf(),
+            tag_rs,
+            tags: &config.tags,
+            prefix: config.tag_prefix.as_deref().unwrap_or("@"),
+            define: &config.define,
+            queued: VecDeque::new(),
+        };
+    }
+
+    /// Reads the next comment, queueing the doc blocks that its `end` lines (or `---` lines
+    /// within an `@if` section) split it into, without the `@if` sections for names that aren't defined; returns `false`
+    /// once there are no comments left
+    fn read_comment(&mut self) -> bool {
+        let mut read_any = false;
+        let mut lines = Vec::new();
+        for comment in &mut self.comments {
+            read_any = true;
+            if comment.last {
+                break;
+            }
+            lines.push(comment);
+        }
+        if !read_any {
+            return false;
+        }
+        let opener = (self.comments.config.start.as_ref()).map(|_| self.comments.opened);
+        let mut start = self.comments.opened;
+        let mut block = Vec::new();
+        // whether each `@if` section the current line is in is included, and its line
+        let mut sections: Vec<(bool, usize)> = Vec::new();
+        for comment in lines {
+            let text = comment.value.trim();
+            let directive = match comment.verbatim {
+                true => None,
+                false => self.directive(&comment.value),
+            };
+            match directive
+                .as_ref()
+                .map(|(tag, value)| (tag.as_str(), value.as_str()))
+            {
+                Some(("if", name)) => {
+                    let defined = self.define.iter().any(|d| d == name.trim());
+                    sections.push((defined, comment.line));
+                    continue;
+                }
+                Some(("else", _)) if !sections.is_empty() => {
+                    let last = sections.len() - 1;
+                    sections[last].0 = !sections[last].0;
+                    continue;
+                }
+                Some(("endif", _)) => {
+                    if sections.pop().is_none() {
+                        warn!(file:% = self.file.display(), line = comment.line; "`@endif` without an `@if`");
+                    }
+                    continue;
+                }
+                _ => (),
+            }
+            if sections.iter().any(|(included, _)| !included) {
+                continue;
+            }
+            let end = text.strip_prefix(self.prefix) == Some("end");
+            // `---` is otherwise a markdown rule
+            let rule = text == "---" && !sections.is_empty();
+            if !comment.verbatim && (end || rule) {
+                dedent(&mut block, opener);
+                self.queued.push_back((block, (start, comment.line)));
+                start = comment.line;
+                block = Vec::new();
+                continue;
+            }
+            block.push(comment);
+        }
+        for (_, line) in sections {
+            warn!(file:% = self.file.display(), line = line; "`@if` without an `@endif`");
+        }
+        dedent(&mut block, opener);
+        self.queued
+            .push_back((block, (start, self.comments.closed)));
+        return true;
+    }
+
+    /// The tag and value of the `@if NAME`, `@else` or `@endif` on `line`, if it is one: read
+    /// by the tag patterns, or as a bare tag on a line of its own (as `@nodoc` is)
+    fn directive(&self, line: &str) -> Option<(String, String)> {
+        let (tag, value) = match self.tag_rs.iter().find_map(|r| r.captures(line)) {
+            Some(m) => (self.tags.name(&m["tag"]), String::from(m["value"].trim())),
+            None => (
+                self.tags.name(line.trim().strip_prefix(self.prefix)?),
+                String::new(),
+            ),
+        };
+        return ["if", "else", "endif"]
+            .contains(&tag.as_str())
+            .then_some((tag, value));
+    }
+
+    /// The lines between `BEGIN <region>` and `END <region>` (e.g. `// BEGIN example`) in the
+    /// file named by `@snippet <file> <region>` on `line`, as a fenced code block
+    fn snippet(
+        &self,
+        line: usize,
+        value: &str,
+        includes: &mut Vec<PathBuf>,
+    ) -> Result<String, SrcDocError> {
+        let error = |msg: String| {
+            let e = SrcDocError::new(msg).with_file(&self.file).with_line(line);
+            return e.with_kind(ErrorKind::Source);
+        };
+        let Some((target, region)) = value.trim().rsplit_once(char::is_whitespace) else {
+            return Err(error(format!(
+                "Expected `@snippet <file> <region>`, found `@snippet {}`.",
+                value.trim()
+            )));
+        };
+        let target = target.trim();
+        let path = self.file.parent().unwrap_or(Path::new("")).join(target);
+        let text = fs::read_to_string(&path)
+            .map_err(|e| error(format!("Could not read the snippet `{}`: {}", target, e)))?
+            .replace(INVISIBLE_CHARS, "");
+        includes.push(fs::canonicalize(&path).unwrap_or(path.clone()));
+
+        let marker = |kind: &str| {
+            let pattern = format!(r"(?:^|\s){}\s+{}(?:\s|$)", kind, regex::escape(region));
+            return Regex::new(&pattern).unwrap();
+        };
+        let (begin, end) = (marker("BEGIN"), marker("END"));
+        let lines: Vec<_> = text.lines().collect();
+        let Some(start) = lines.iter().position(|l| begin.is_match(l)).map(|i| i + 1) else {
+            return Err(error(format!(
+                "No `BEGIN {}` line in `{}`.",
+                region, target
+            )));
+        };
+        let Some(len) = lines[start..].iter().position(|l| end.is_match(l)) else {
+            return Err(error(format!(
+                "No `END {}` line after `BEGIN {}` in `{}`.",
+                region, region, target
+            )));
+        };
+        let code = &lines[start..start + len];
+        let indent = common_indent(code.iter().copied());
+        let extension = path.extension().map_or(String::new(), |e| {
+            return String::from(e.to_string_lossy());
+        });
+        let language = (FENCE_LANGUAGES.iter())
+            .find(|(ext, _)| *ext == extension)
+            .map_or(extension.as_str(), |(_, language)| language);
+        let mut result = format!("```{}\n", language);
+        for code_line in code {
+            result.push_str(code_line.get(indent..).unwrap_or_default());
+            result.push('\n');
+        }
+        result.push_str("```\n");
+        return Ok(result);
+    }
+
+    /// The text of the file `target` named by an `@include` on `line` of `from` (and
+    /// relative to it), with the `@include` tags within it replaced in turn; `stack` holds
+    /// the files being included, to catch cycles, and `includes` collects every file read
+    fn include(
+        &self,
+        from: &Path,
+        line: usize,
+        target: &str,
+        stack: &mut Vec<PathBuf>,
+        includes: &mut Vec<PathBuf>,
+    ) -> Result<String, SrcDocError> {
+        let error = |msg: String| {
+            let e = SrcDocError::new(msg).with_file(from).with_line(line);
+            return e.with_kind(ErrorKind::Source);
+        };
+        let path = from.parent().unwrap_or(Path::new("")).join(target);
+        let read = fs::canonicalize(&path).and_then(|p| Ok((fs::read_to_string(&p)?, p)));
+        let (text, canonical) =
+            read.map_err(|e| error(format!("Could not include `{}`: {}", target, e)))?;
+        if stack.contains(&canonical) {
+            return Err(error(format!(
+                "Could not include `{}`: it includes itself.",
+                target
+            )));
+        }
+        includes.push(canonical.clone());
+        stack.push(canonical);
+        let mut result = String::new();
+        let text = text.replace(INVISIBLE_CHARS, "");
+        for (i, text_line) in text.lines().enumerate() {
+            let nested = (self.tag_rs.iter())
+                .find_map(|r| r.captures(text_line))
+                .filter(|m| self.tags.name(&m["tag"]) == "include");
+            match nested {
+                Some(m) => result.push_str(&self.include(
+                    &path,
+                    i + 1,
+                    m["value"].trim(),
+                    stack,
+                    includes,
+                )?),
+                None => {
+                    result.push_str(text_line);
+                    result.push('\n');
+                }
+            }
+        }
+        stack.pop();
+        return Ok(result);
+    }
+}
+
+/// The tags of a doc block, as they are read from a comment or from the front matter of a
+/// markdown page
+#[derive(Default)]
+struct BlockTags {
+    tags: HashMap<String, String>,
+    order: OrderKey,
+    /// the block has the `nodoc` tag, and is left out of the docs
+    nodoc: bool,
+    lists: BTreeMap<String, Vec<TagItem>>,
+    /// every value of each tag, in order
+    values: BTreeMap<String, Vec<String>>,
+    /// the lines each tag is found on, used when reporting invalid values
+    lines: HashMap<String, Vec<usize>>,
+}
+
+impl BlockTags {
+    /// Adds the `value` of `tag` (a name read by `TagsConfig::name`), found on `line` of `file`
+    fn add(
+        &mut self,
+        config: &TagsConfig,
+        tag: &str,
+        value: &str,
+        file: &Path,
+        line: usize,
+    ) -> Result<(), SrcDocError> {
+        if tag == "__body__" {
+            return Err(
+                SrcDocError::new(String::from("The tag `__body__` is reserved."))
+                    .with_file(file)
+                    .with_line(line)
+                    .with_kind(ErrorKind::Source),
+            );
+        } else if tag == "order" {
+            self.order = parse_order(value, config.order, Some((file, line)));
+        } else if tag == config.nodoc() {
+            self.nodoc = true;
+        }
+        if let Some(list) = config.list(tag) {
+            let item = TagItem::parse(value, list == TagList::Named);
+            self.lists.entry(String::from(tag)).or_default().push(item);
+        }
+        self.values
+            .entry(String::from(tag))
+            .or_default()
+            .push(String::from(value));
+        match self.tags.get_mut(tag) {
+            // a block can be written to several files (see `DocData::files`)
+            Some(files) if tag == "file" => {
+                files.push_str(", ");
+                files.push_str(value);
+            }
+            _ => {
+                self.tags.insert(String::from(tag), String::from(value));
+            }
+        }
+        self.lines.entry(String::from(tag)).or_default().push(line);
+        return Ok(());
+    }
+
+    /// Continues the last value of `tag` with `text`, as a new paragraph if `paragraph`
+    fn continue_value(&mut self, tag: &str, text: &str, paragraph: bool) {
+        let continue_value = |value: &mut String| {
+            if paragraph {
+                value.push_str("\n\n");
+            } else if !value.is_empty() {
+                value.push(' ');
+            }
+            value.push_str(text);
+        };
+        self.tags
+            .entry(String::from(tag))
+            .and_modify(continue_value);
+        if let Some(item) = self.lists.get_mut(tag).and_then(|l| l.last_mut()) {
+            continue_value(&mut item.description);
+        }
+        if let Some(value) = self.values.get_mut(tag).and_then(|v| v.last_mut()) {
+            continue_value(value);
+        }
+    }
+
+    /// Checks every value against the `validate` table of `config`
+    fn validate(&self, config: &TagsConfig, file: &Path) -> Result<(), SrcDocError> {
+        for (tag, check) in &config.validate {
+            let Some(values) = self.values.get(tag) else {
+                continue;
+            };
+            for (value, line) in values.iter().zip(&self.lines[tag]) {
+                if let Some(msg) = check.problem(tag, value) {
+                    return Err(SrcDocError::new(msg)
+                        .with_file(file)
+                        .with_line(*line)
+                        .with_kind(ErrorKind::Source));
+                }
+            }
+        }
+        return Ok(());
+    }
+
+    /// The doc block with these tags, whose `body` is read from `file` starting on `line`
+    fn into_doc(
+        self,
+        body: String,
+        file: &Path,
+        line: usize,
+        span: (usize, usize),
+        includes: Vec<PathBuf>,
+    ) -> DocData {
+        let repeated = self
+            .values
+            .into_iter()
+            .filter(|(_, v)| v.len() > 1)
+            .collect();
+        return DocData {
+            tags: self.tags,
+            order: self.order,
+            body,
+            file: file.to_path_buf(),
+            line,
+            signature: None,
+            span,
+            source_file: PathBuf::new(),
+            section: None,
+            includes,
+            lists: self.lists,
+            repeated,
+        };
+    }
+}
+
+impl<'a, T: Iterator<Item = String>> Iterator for DocIterator<'a, T> {
+    type Item = Result<DocData, SrcDocError>;
+    fn next(&mut self) -> Option<Result<DocData, SrcDocError>> {
+        // blocks that are skipped move on to the next one
+        loop {
+            let mut body = String::new();
+            let mut tags = BlockTags::default();
+            let mut available_data = false;
+            let mut last_tag: Option<String> = None;
+            // a blank line was found in the value of `last_tag` (see `TagContinuation::Tag`)
+            let mut paragraph = false;
+            let mut line = None;
+            let mut includes = Vec::new();
+
+            if self.queued.is_empty() && !self.read_comment() {
+                return None;
+            }
+            let (lines, span) = self.queued.pop_front().unwrap();
+            for comment in lines {
+                line.get_or_insert(comment.line);
+
+                if comment.verbatim {
                     available_data = true;
                     last_tag = None;
-                    body.push('\n');
-                } else if let Some(tag) = &last_tag {
-                    tags.entry(tag.clone()).and_modify(|value| {
-                        value.push(' ');
-                        value.push_str(&comment.value);
-                    });
-                } else {
-                    available_data = true;
                     body.push_str(&comment.value);
                     body.push('\n');
+                    continue;
+                }
+                let bare = comment.value.trim().strip_prefix(self.prefix);
+                if bare.is_some_and(|t| self.tags.name(t) == self.tags.nodoc()) {
+                    tags.nodoc = true;
+                    continue;
+                }
+                let captures = self.tag_rs.iter().find_map(|r| r.captures(&comment.value));
+                if let Some(m) = captures {
+                    let tag = &self.tags.name(&m["tag"]);
+                    if tag == "include" {
+                        let mut stack: Vec<_> = fs::canonicalize(&self.file).into_iter().collect();
+                        match self.include(
+                            &self.file,
+                            comment.line,
+                            m["value"].trim(),
+                            &mut stack,
+                            &mut includes,
+                        ) {
+                            Ok(text) => body.push_str(&text),
+                            Err(e) => return Some(Err(e)),
+                        }
+                        available_data = true;
+                        last_tag = None;
+                        continue;
+                    }
+                    if tag == "snippet" {
+                        match self.snippet(comment.line, &m["value"], &mut includes) {
+                            Ok(text) => body.push_str(&text),
+                            Err(e) => return Some(Err(e)),
+                        }
+                        available_data = true;
+                        last_tag = None;
+                        continue;
+                    }
+                    let value = m["value"].trim();
+                    if let Err(e) = tags.add(self.tags, tag, value, &self.file, comment.line) {
+                        return Some(Err(e));
+                    }
+                    let continued = tag != "order" && tag != self.tags.nodoc();
+                    if continued && self.tags.continuation != TagContinuation::None {
+                        last_tag = Some(String::from(tag));
+                        paragraph = false;
+                    }
+                    if self.tags.keep.iter().any(|t| t == tag) {
+                        available_data = true;
+                        body.push_str(&format!("**{}:** {}\n", tag, value));
+                    }
+                } else {
+                    let blank = comment.value.trim().is_empty();
+                    if blank && self.tags.continuation == TagContinuation::Tag && last_tag.is_some()
+                    {
+                        paragraph = true;
+                        if last_tag
+                            .as_ref()
+                            .is_some_and(|t| self.tags.keep.contains(t))
+                        {
+                            body.push('\n');
+                        }
+                    } else if blank {
+                        available_data = true;
+                        last_tag = None;
+                        body.push('\n');
+                    } else if let Some(tag) = &last_tag {
+                        tags.continue_value(tag, comment.value.trim_start(), paragraph);
+                        paragraph = false;
+                        if self.tags.keep.contains(tag) {
+                            body.push_str(&comment.value);
+                            body.push('\n');
+                        }
+                    } else {
+                        available_data = true;
+                        body.push_str(&comment.value);
+                        body.push('\n');
+                    }
+                }
+            }
+
+            if tags.nodoc {
+                continue;
+            }
+            // blocks holding only tags (such as PowerShell help) are kept for the templates
+            if available_data || !tags.tags.is_empty() {
+                if let Err(e) = tags.validate(self.tags, &self.file) {
+                    return Some(Err(e));
                 }
+                let line = line.unwrap_or_default();
+                return Some(Ok(tags.into_doc(body, &self.file, line, span, includes)));
+            } else {
+                // skip empty blocks
+                continue;
             }
         }
+    }
+}
 
-        if available_data {
-            return Some(DocData { tags, order, body });
-        } else {
-            return None;
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A directory of scratch files, deleted when dropped
+    struct Scratch(PathBuf);
+
+    impl Scratch {
+        fn new() -> Scratch {
+            static COUNT: AtomicUsize = AtomicUsize::new(0);
+            let n = COUNT.fetch_add(1, atomic::Ordering::SeqCst);
+            let name = format!("simple-src-docs-test-{}-{}", std::process::id(), n);
+            let dir = std::env::temp_dir().join(name);
+            fs::create_dir_all(&dir).unwrap();
+            return Scratch(dir);
+        }
+
+        fn write(&self, file: &str, text: &str) -> PathBuf {
+            let path = self.0.join(file);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(&path, text).unwrap();
+            return path;
+        }
+    }
+
+    impl Drop for Scratch {
+        fn drop(&mut self) {
+            let _ = fs::remove_dir_all(&self.0);
+        }
+    }
+
+    /// The config file `text` (followed by a `[header]`), read with `overrides`
+    fn config(text: &str, overrides: &[&str]) -> SrcDocConfig {
+        let dir = Scratch::new();
+        let text = format!("{}\n[header]\nversion = \"0.2.1\"\n", text);
+        let path = dir.write("config.toml", &text);
+        let overrides: Vec<_> = overrides.iter().map(|s| s.to_string()).collect();
+        return SrcDocConfig::from(Some(&path), &overrides).unwrap();
+    }
+
+    /// The doc blocks read from `text`, as the content of `file`
+    fn read_with(config: &SrcDocConfig, file: &str, text: &str) -> Vec<DocData> {
+        let source = read_comment_lines(config, Path::new(file), text.as_bytes()).unwrap();
+        return source.docs;
+    }
+
+    fn read(file: &str, text: &str) -> Vec<DocData> {
+        return read_with(&SrcDocConfig::from(None, &[]).unwrap(), file, text);
+    }
+
+    fn bodies(docs: &[DocData]) -> Vec<&str> {
+        return docs.iter().map(|d| d.body.trim()).collect();
+    }
+
+    #[test]
+    fn nested_rust_comments() {
+        let docs = read(
+            "a.rs",
+            "/**\n * @file a.md\n *\n * before /* nested */ after\n * last\n */\nfn a() {}\n",
+        );
+        assert_eq!(bodies(&docs), ["before /* nested */ after\nlast"]);
+    }
+
+    #[test]
+    fn nested_julia_comments() {
+        let docs = read(
+            "a.jl",
+            "#=\n@file a.md\n\nouter #= inner =# still\nouter\n=#\nf() = 1\n",
+        );
+        assert_eq!(bodies(&docs), ["outer #= inner =# still\nouter"]);
+    }
+
+    #[test]
+    fn dedent() {
+        let docs = read(
+            "a.py",
+            "def f():\n    \"\"\"\n    @file a.md\n\n    Some text\n        indented\n    \"\"\"\n",
+        );
+        assert_eq!(docs[0].body, "\nSome text\n    indented\n");
+    }
+
+    #[test]
+    fn same_line_comments() {
+        let docs = read(
+            "a.c",
+            "/** one line */\nint x;\n/** @file a.md */\nint y;\n",
+        );
+        assert_eq!(bodies(&docs), ["one line", ""]);
+        assert_eq!(docs[1].tags["file"], "a.md");
+    }
+
+    #[test]
+    fn shebang_lines() {
+        let script = "#!/bin/bash\n# @file a.md\n#\n# Shell docs\necho\n";
+        assert_eq!(bodies(&read("a.sh", script)), ["Shell docs"]);
+        // files without an extension are read with the syntax of their interpreter
+        assert_eq!(bodies(&read("script", script)), ["Shell docs"]);
+        let docs = read("a.jl", "#!/usr/bin/env julia\n#\n# @file a.md\n#\n# docs\n");
+        assert_eq!(bodies(&docs), ["docs"]);
+    }
+
+    #[test]
+    fn triple_slash_lines() {
+        let text = "/// <reference path=\"a.d.ts\" />\n/// <amd-module name=\"a\"/>\n\
+                    /// @file a.md\n///\n/// docs\nlet x = 1;\n///< about x\n";
+        assert_eq!(bodies(&read("a.ts", text)), ["docs"]);
+        let text = "/// <summary>\n/// Adds <c>x</c>.\n/// </summary>\n\
+                    /// <param name=\"x\">the value</param>\nint Add(int x);\n";
+        let docs = read("a.cs", text);
+        assert_eq!(
+            bodies(&docs),
+            ["<summary>\nAdds <c>x</c>.\n</summary>\n<param name=\"x\">the value</param>"]
+        );
+        assert_eq!(not_starting_with(&["ab"]), "(?:$|[^a].*|a(?:$|[^b].*))");
+    }
+
+    #[test]
+    fn rust_signatures() {
+        let signature = |item: &str| {
+            let docs = read("a.rs", &format!("/// @file a.md\n///\n/// x\n{}\n", item));
+            return docs[0].signature.clone();
+        };
+        assert_eq!(
+            signature("#[inline]\npub fn f() -> u8 {").as_deref(),
+            Some("pub fn f() -> u8")
+        );
+        assert_eq!(
+            signature("fn f(x: [u8; 4]) -> T {").as_deref(),
+            Some("fn f(x: [u8; 4]) -> T")
+        );
+        assert_eq!(
+            signature("fn g() -> Vec<[u8; 2]>;").as_deref(),
+            Some("fn g() -> Vec<[u8; 2]>")
+        );
+        assert_eq!(
+            signature("fn h<T>(\n    x: [T; 2],\n) -> T\nwhere\n    T: Copy,\n{").as_deref(),
+            Some("fn h<T>( x: [T; 2], ) -> T where T: Copy,")
+        );
+        assert_eq!(
+            signature("const N: [u8; 2] = [1, 2];").as_deref(),
+            Some("const N: [u8; 2] = [1, 2]")
+        );
+    }
+
+    #[test]
+    fn tag_continuation() {
+        let docs = read(
+            "a.rs",
+            "/// @param x the\n///   x value\n/// @returns y\n///\n/// body\nfn f() {}\n",
+        );
+        assert_eq!(docs[0].tags["param"], "x the x value");
+        assert_eq!(docs[0].lists["param"][0].name.as_deref(), Some("x"));
+        assert_eq!(docs[0].lists["param"][0].description, "the x value");
+        assert_eq!(docs[0].tags["returns"], "y");
+        assert_eq!(docs[0].body.trim(), "body");
+    }
+
+    #[test]
+    fn block_tags() {
+        let config = config("[tags.validate]\nlevel = { enum = [\"a\", \"b\"] }\n", &[]);
+        let file = Path::new("a.rs");
+        let mut tags = BlockTags::default();
+        tags.add(&config.tags, "see", "x", file, 1).unwrap();
+        tags.add(&config.tags, "see", "y", file, 2).unwrap();
+        tags.add(&config.tags, "order", "2", file, 3).unwrap();
+        tags.continue_value("see", "z", false);
+        tags.add(&config.tags, "level", "c", file, 4).unwrap();
+        let e = tags.validate(&config.tags, file).unwrap_err();
+        assert_eq!(e.line, Some(4));
+        assert!(tags.add(&config.tags, "__body__", "x", file, 5).is_err());
+
+        tags.values.remove("level");
+        let doc = tags.into_doc(String::new(), file, 1, (1, 5), Vec::new());
+        assert_eq!(doc.tags["see"], "y z");
+        assert_eq!(doc.repeated["see"], ["x", "y z"]);
+        assert!(doc.order == OrderKey::Number(2.0));
+    }
+
+    #[test]
+    fn nodoc() {
+        let docs = read(
+            "a.rs",
+            "/// @nodoc\n/// hidden\nfn f() {}\n\n/// shown\nfn g() {}\n",
+        );
+        assert_eq!(bodies(&docs), ["shown"]);
+    }
+
+    #[test]
+    fn order() {
+        let number = |s| OrderKey::parse(s, OrderStyle::Number).unwrap();
+        let dotted = |s| OrderKey::parse(s, OrderStyle::Dotted).unwrap();
+        assert!(number("2.9") > number("2.10"));
+        assert!(number("-1") < number("0.5"));
+        assert!(dotted("2.9") < dotted("2.10"));
+        assert!(dotted("2") < dotted("2.1"));
+        assert!(dotted("1.5") < OrderKey::Number(2.0));
+        assert!(OrderKey::parse("-1.2", OrderStyle::Dotted).is_err());
+        assert!(OrderKey::parse("x", OrderStyle::Number).is_err());
+
+        let config = config("[tags]\norder = \"dotted\"\n", &[]);
+        let docs = read_with(&config, "a.rs", "/// @order 1.10\n///\n/// x\nfn f() {}\n");
+        assert!(docs[0].order == OrderKey::Dotted(vec![1, 10]));
+    }
+
+    #[test]
+    fn split_blocks() {
+        let docs = read(
+            "a.rs",
+            "/// @order 1\n///\n/// one\n/// @end\n/// @order 2\n///\n/// two\nfn f() {}\n",
+        );
+        assert_eq!(bodies(&docs), ["one", "two"]);
+        assert!(docs[1].order == OrderKey::Number(2.0));
+
+        // a `---` outside of an `@if` section is a markdown rule
+        let docs = read(
+            "a.rs",
+            "/// @file a.md\n///\n/// one\n/// ---\n/// two\nfn f() {}\n",
+        );
+        assert_eq!(bodies(&docs), ["one\n---\ntwo"]);
+        let config = config("define = [\"x\"]\n", &[]);
+        let text = "/// @file a.md\n///\n/// @if x\n/// one\n/// ---\n/// @order 2\n///\n/// two\n\
+                    /// @endif\nfn f() {}\n";
+        assert_eq!(bodies(&read_with(&config, "a.rs", text)), ["one", "two"]);
+    }
+
+    #[test]
+    fn conditional_sections() {
+        let text = "/// @file a.md\n///\n/// @if internal\n/// secret\n/// @else\n/// public\n\
+                    /// @endif\n/// always\nfn f() {}\n";
+        let docs = read("a.rs", text);
+        assert_eq!(bodies(&docs), ["public\nalways"]);
+        let config = config("define = [\"internal\"]\n", &[]);
+        let docs = read_with(&config, "a.rs", text);
+        assert_eq!(bodies(&docs), ["secret\nalways"]);
+
+        // only tags read by the tag pattern are directives
+        let custom = super::tests::config("tag_prefix = \"\\\\\"\n", &[]);
+        let text = "/// \\file a.md\n///\n/// @if x\n/// \\if x\n/// y\n/// \\endif\nfn f() {}\n";
+        assert_eq!(bodies(&read_with(&custom, "a.rs", text)), ["@if x"]);
+    }
+
+    #[test]
+    fn notebook_lines() {
+        let text = "{\n \"cells\": [\n  {\"cell_type\": \"markdown\", \"metadata\": {},\n   \"source\": [\n    \
+                    \"@file a.md\\n\",\n    \"\\n\",\n    \"one\\n\",\n    \"two\"\n   ]},\n  \
+                    {\"cell_type\": \"code\", \"metadata\": {\"tags\": [\"doc\"]}, \
+                    \"source\": \"# @file b.md\\n#\\n# three\\nx = 1\"}\n ]\n}\n";
+        let docs = read("a.ipynb", text);
+        assert_eq!(bodies(&docs), ["one\ntwo", "three"]);
+        assert_eq!((docs[0].line, docs[0].span), (5, (5, 8)));
+        assert_eq!((docs[1].line, docs[1].span), (10, (10, 10)));
+    }
+
+    #[test]
+    fn file_lists() {
+        let docs = read(
+            "a.rs",
+            "/// @file a.md, b.md\n/// @file c.md\n/// @file d,e.md,\n///\n/// x\nfn f() {}\n",
+        );
+        assert_eq!(docs[0].files(), ["a.md", "b.md", "c.md", "d,e.md"]);
+    }
+
+    #[test]
+    fn man_configuration_section() {
+        let page = man_configuration();
+        assert!(page.starts_with(".SH CONFIGURATION\n.PP\nThe config file is a TOML file"));
+        assert!(page.contains(".TP\n\\fBheader\\fR\nmust contain a \\fBversion\\fR string"));
+        assert!(page.contains(".RS\n.TP\n\\fBforeach\\fR\n"));
+        assert!(!page.contains('`') && !page.contains("](") && !page.contains("Example config"));
+        assert_eq!(page.matches(".RS\n").count(), page.matches(".RE\n").count());
+    }
+
+    #[test]
+    fn cache_hash() {
+        // the hashes stored in caches must not change between builds
+        assert_eq!(content_hash(b""), 0xcbf29ce484222325);
+        assert_eq!(content_hash(b"a"), 0xaf63dc4c8601ec8c);
+        assert_ne!(content_hash(b"ab"), content_hash(b"ba"));
+    }
+
+    #[test]
+    fn config_include() {
+        let dir = Scratch::new();
+        dir.write(
+            "shared/more.toml",
+            "ignore = [\"b\"]\nvariables = { x = \"included\", y = \"included\" }\n",
+        );
+        let path = dir.write(
+            "config.toml",
+            "include = [\"shared/more.toml\"]\nignore = [\"a\"]\nvariables = { x = \"main\" }\n\
+             [header]\nversion = \"0.2.1\"\n",
+        );
+        let config = SrcDocConfig::from(Some(&path), &[]).unwrap();
+        // arrays are appended to, and the including file's settings take precedence
+        assert_eq!(config.ignore, ["a", "b"]);
+        assert_eq!(config.variables["x"], "main");
+        assert_eq!(config.variables["y"], "included");
+    }
+
+    #[test]
+    fn config_errors() {
+        // problems are found in the file they are in, before it is merged with others
+        let dir = Scratch::new();
+        let included = dir.write("more.toml", "ignore = [\"b\"]\nignored = [\"c\"]\n");
+        let path = dir.write(
+            "config.toml",
+            "include = [\"more.toml\"]\n[header]\nversion = \"0.2.1\"\n",
+        );
+        let error = SrcDocConfig::from(Some(&path), &[]).err().unwrap();
+        assert_eq!((error.file, error.line), (Some(included), Some(2)));
+        assert!(error.msg.contains("did you mean `ignore`?"));
+
+        let path = dir.write(
+            "child.toml",
+            "strict = 1\n[header]\nversion = \"0.2.1\"\nextends = \"config.toml\"\n",
+        );
+        let error = SrcDocConfig::from(Some(&path), &["strict=true".into()])
+            .err()
+            .unwrap();
+        assert_eq!((error.file, error.line), (Some(path), Some(1)));
+    }
+
+    #[test]
+    fn config_extends() {
+        let dir = Scratch::new();
+        dir.write(
+            "base.toml",
+            "ignore = [\"b\"]\nvariables = { x = \"base\", y = \"base\" }\n\
+             [header]\nversion = \"0.2.1\"\n",
+        );
+        let path = dir.write(
+            "config.toml",
+            "ignore = [\"a\"]\nvariables = { x = \"child\" }\n\
+             [header]\nversion = \"0.2.1\"\nextends = \"base.toml\"\n",
+        );
+        let config = SrcDocConfig::from(Some(&path), &[]).unwrap();
+        // arrays are replaced, and tables merged
+        assert_eq!(config.ignore, ["a"]);
+        assert_eq!(config.variables["x"], "child");
+        assert_eq!(config.variables["y"], "base");
+    }
+
+    #[test]
+    fn config_section_paths() {
+        let dir = Scratch::new();
+        dir.write(
+            "shared/more.toml",
+            "[[source]]\npath = \"../lib\"\n[[dest]]\npattern = \"*\"\npath = \"site\"\n",
+        );
+        let path = dir.write(
+            "docs/config.toml",
+            "include = [\"../shared/more.toml\"]\n[[source]]\npath = \"../front\"\n\
+             [header]\nversion = \"0.2.1\"\n",
+        );
+        let front = dir.write("front/a.rs", "");
+        let lib = dir.write("lib/a.rs", "");
+        // paths are relative to the file that gives them, wherever the tool is run from
+        let config = SrcDocConfig::from(Some(&path), &[]).unwrap();
+        assert_eq!(config.find_source_config(&front), Some(0));
+        assert_eq!(config.find_source_config(&lib), Some(1));
+        assert_eq!(config.find_source_config(&dir.0.join("docs/a.rs")), None);
+        assert_eq!(
+            config.dest_for(Path::new("a.md")),
+            Some(&*dir.0.join("docs/../shared/site"))
+        );
+    }
+
+    #[test]
+    fn glob_flags() {
+        for glob in [
+            "*.md",
+            "**/docs/*.md",
+            "(?i)*.md",
+            "**/(?i)a/*.md",
+            "a/(?i)b",
+        ] {
+            assert_eq!(case_sensitive(&case_insensitive(glob)), glob);
         }
+        let dest: DestConfig = toml::from_str("pattern = \"**/Docs/*.md\"\npath = \"x\"").unwrap();
+        assert!(dest.pattern.is_match(Path::new("src/docs/a.MD")));
+        let text = toml::to_string(&dest).unwrap();
+        assert!(text.contains("pattern = \"**/Docs/*.md\""));
+    }
+
+    #[test]
+    fn config_overrides() {
+        let config = config(
+            "ignore = [\"a\"]\n",
+            &[
+                "variables.x=one",
+                "ignore.1=b",
+                "tags.case_insensitive=true",
+            ],
+        );
+        assert_eq!(config.variables["x"], "one");
+        assert_eq!(config.ignore, ["a", "b"]);
+        assert!(config.tags.case_insensitive);
+
+        let overrides = [String::from("ignore.5=b")];
+        assert!(SrcDocConfig::from(None, &overrides).is_err());
     }
 }